@@ -0,0 +1,93 @@
+mod inner {
+    use anyhow::anyhow;
+    use std::io::{IsTerminal, Write};
+    use std::path::{Path, PathBuf};
+
+    /// `$XDG_CONFIG_HOME/teamspeak-input-helper/apikey`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("apikey"))
+    }
+
+    fn check_key(key: &str) -> anyhow::Result<String> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow!("API key is empty"));
+        }
+        Ok(key.to_string())
+    }
+
+    /// Read key from file, refuse file which can be accessed by other users.
+    pub fn read_file(path: &Path) -> anyhow::Result<String> {
+        crate::private_file::check(path, "API key file")?;
+        check_key(
+            &std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Unable read API key file {}: {}", path.display(), e))?,
+        )
+    }
+
+    /// Read first line of stdin, the rest is left for input thread.
+    pub fn read_stdin() -> anyhow::Result<String> {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .map_err(|e| anyhow!("Unable read API key from stdin: {}", e))?;
+        check_key(&line)
+    }
+
+    fn save(path: &Path, key: &str) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = crate::private_file::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        writeln!(file, "{}", key)?;
+        Ok(())
+    }
+
+    /// Read key from default file, ask user and save it if file not exists.
+    pub fn load_or_prompt() -> anyhow::Result<String> {
+        let path = default_path();
+        if let Some(path) = path.as_deref().filter(|path| path.exists()) {
+            return read_file(path);
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(anyhow!(
+                "API key is not specified, use --api-key-file, --api-key-stdin or config file"
+            ));
+        }
+        let key = check_key(&rpassword::prompt_password(
+            "Teamspeak client query API key: ",
+        )?)?;
+        if let Some(path) = path {
+            match save(&path, &key) {
+                Ok(_) => println!("[*] API key saved to {}", path.display()),
+                Err(e) => println!("[!] Unable save API key to {}: {}", path.display(), e),
+            }
+        }
+        Ok(key)
+    }
+}
+
+pub use inner::{load_or_prompt, read_file, read_stdin};
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::read_file;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_read_file_permission() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("apikey");
+        std::fs::write(&path, "ABCD-1234\n").unwrap();
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(read_file(&path).is_err());
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(read_file(&path).unwrap(), "ABCD-1234");
+    }
+}
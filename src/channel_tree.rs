@@ -0,0 +1,113 @@
+mod inner {
+    use crate::datastructures::{ClientInfo, ServerTables};
+
+    const INDENT: &str = "  ";
+
+    /// Nickname with flags, e.g. `Alice [away: brb, mic muted]`.
+    fn describe_client(client: &ClientInfo, own: bool) -> String {
+        let mut flags = Vec::new();
+        if client.is_talking() {
+            flags.push("talking".to_string());
+        }
+        if client.is_away() {
+            flags.push(match client.away_message() {
+                "" => "away".to_string(),
+                message => format!("away: {}", message),
+            });
+        }
+        if client.is_input_muted() {
+            flags.push("mic muted".to_string());
+        }
+        if client.is_output_muted() {
+            flags.push("sound muted".to_string());
+        }
+        let mut line = client.nickname().to_string();
+        if own {
+            line.push_str(" (you)");
+        }
+        if !flags.is_empty() {
+            line.push_str(&format!(" [{}]", flags.join(", ")));
+        }
+        line
+    }
+
+    /// Clients in channel, one per line, ServerQuery clients are hidden.
+    pub fn render_clients(
+        tables: &ServerTables,
+        channel_id: i64,
+        own_client: Option<i64>,
+        depth: usize,
+    ) -> Vec<String> {
+        tables
+            .clients_in(channel_id)
+            .into_iter()
+            .filter(|client| client.is_client())
+            .map(|client| {
+                format!(
+                    "{}- {}",
+                    INDENT.repeat(depth),
+                    describe_client(client, own_client == Some(client.client_id()))
+                )
+            })
+            .collect()
+    }
+
+    fn render_channel(
+        tables: &ServerTables,
+        parent_id: i64,
+        own_client: Option<i64>,
+        depth: usize,
+        lines: &mut Vec<String>,
+    ) {
+        for channel in tables.children(parent_id) {
+            lines.push(format!("{}{}", INDENT.repeat(depth), channel.name()));
+            lines.extend(render_clients(
+                tables,
+                channel.channel_id(),
+                own_client,
+                depth + 1,
+            ));
+            render_channel(tables, channel.channel_id(), own_client, depth + 1, lines);
+        }
+    }
+
+    /// Channel tree with occupants.
+    pub fn render_tree(tables: &ServerTables, own_client: Option<i64>) -> String {
+        let mut lines = Vec::new();
+        render_channel(tables, 0, own_client, 0, &mut lines);
+        lines.join("\n")
+    }
+}
+
+pub use inner::{render_clients, render_tree};
+
+#[cfg(test)]
+mod test {
+    use super::{render_clients, render_tree};
+    use crate::datastructures::{Channel, Client, FromQueryString, ServerModel};
+
+    #[test]
+    fn test_render_tree() {
+        let mut model = ServerModel::default();
+        model.set_clients(
+            1,
+            &r"clid=1 cid=1 client_nickname=Alice client_type=0 client_flag_talking=1|clid=2 cid=2 client_nickname=Bob client_type=0 client_away=1 client_away_message=brb client_input_muted=1|clid=3 cid=1 client_nickname=serveradmin client_type=1"
+                .split('|')
+                .map(|s| Client::from_query(s).unwrap())
+                .collect::<Vec<_>>(),
+        );
+        model.set_channels(
+            1,
+            &r"cid=1 pid=0 channel_order=0 channel_name=Lobby|cid=2 pid=1 channel_order=0 channel_name=Quiet\sRoom|cid=3 pid=0 channel_order=1 channel_name=Empty"
+                .split('|')
+                .map(|s| Channel::from_query(s).unwrap())
+                .collect::<Vec<_>>(),
+        );
+        let tables = model.server(1).unwrap();
+        assert_eq!(
+            render_tree(tables, Some(2)),
+            "Lobby\n  - Alice [talking]\n  Quiet Room\n    - Bob (you) [away: brb, mic muted]\nEmpty"
+        );
+        assert_eq!(render_clients(tables, 1, None, 0), ["- Alice [talking]"]);
+    }
+}
@@ -0,0 +1,147 @@
+mod inner {
+    use crate::datastructures::TargetMode;
+    use anyhow::anyhow;
+    use chrono::{DateTime, FixedOffset, NaiveDate};
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    /// One line of chat log.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ChatRecord {
+        pub timestamp: DateTime<FixedOffset>,
+        pub schandlerid: i64,
+        pub server: String,
+        /// Unique identifier of server, empty if it is unknown
+        #[serde(default)]
+        pub server_uid: String,
+        pub mode: Option<TargetMode>,
+        pub sender: String,
+        pub sender_uid: String,
+        /// Message sent by ourselves
+        pub outgoing: bool,
+        pub message: String,
+    }
+
+    /// Append text messages to `<dir>/<server uid>/<date>.jsonl`, a new file is used each day.
+    ///
+    /// Server tab id is only valid while tab is opened, so server unique identifier is used to
+    /// keep messages of same server together, messages of unknown server go to `<dir>/unknown`.
+    #[derive(Debug)]
+    pub struct ChatLogger {
+        dir: PathBuf,
+        files: HashMap<PathBuf, (NaiveDate, File)>,
+    }
+
+    impl ChatLogger {
+        pub fn new(dir: PathBuf) -> Self {
+            Self {
+                dir,
+                files: Default::default(),
+            }
+        }
+
+        /// Directory of server, unique identifier is base64 so it is mapped to URL-safe alphabet.
+        pub fn server_dir(dir: &Path, server_uid: &str) -> PathBuf {
+            if server_uid.is_empty() {
+                return dir.join("unknown");
+            }
+            dir.join(server_uid.replace('+', "-").replace('/', "_"))
+        }
+
+        fn open(dir: &Path, date: NaiveDate) -> anyhow::Result<File> {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Unable create log directory {}: {}", dir.display(), e))?;
+            let path = dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")));
+            crate::private_file::options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| anyhow!("Unable open log file {}: {}", path.display(), e))
+        }
+
+        pub fn write(&mut self, record: &ChatRecord) -> anyhow::Result<()> {
+            let date = record.timestamp.date_naive();
+            let dir = Self::server_dir(&self.dir, &record.server_uid);
+            let file = match self.files.get_mut(&dir) {
+                Some((opened, file)) if *opened == date => file,
+                _ => {
+                    let file = Self::open(&dir, date)?;
+                    &mut self
+                        .files
+                        .entry(dir)
+                        .insert_entry((date, file))
+                        .into_mut()
+                        .1
+                }
+            };
+            let mut line = serde_json::to_string(record)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+            Ok(())
+        }
+    }
+}
+
+pub use inner::{ChatLogger, ChatRecord};
+
+#[cfg(test)]
+mod test {
+    use super::{ChatLogger, ChatRecord};
+    use crate::datastructures::TargetMode;
+    use chrono::DateTime;
+
+    #[test]
+    fn test_rotate_daily() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let mut logger = ChatLogger::new(dir.to_path_buf());
+        let mut record = ChatRecord {
+            timestamp: DateTime::parse_from_rfc3339("2024-01-01T23:59:59+08:00").unwrap(),
+            schandlerid: 1,
+            server: "Test".to_string(),
+            server_uid: "abc+/=".to_string(),
+            mode: Some(TargetMode::Channel),
+            sender: "Alice".to_string(),
+            sender_uid: "uid=".to_string(),
+            outgoing: false,
+            message: "hello\nworld".to_string(),
+        };
+        logger.write(&record).unwrap();
+        record.timestamp = DateTime::parse_from_rfc3339("2024-01-02T00:00:01+08:00").unwrap();
+        logger.write(&record).unwrap();
+        logger.write(&record).unwrap();
+
+        let server_dir = dir.join("abc-_=");
+        assert_eq!(ChatLogger::server_dir(dir, &record.server_uid), server_dir);
+        let first = std::fs::read_to_string(server_dir.join("2024-01-01.jsonl")).unwrap();
+        let second = std::fs::read_to_string(server_dir.join("2024-01-02.jsonl")).unwrap();
+        assert_eq!(first.lines().count(), 1);
+        assert_eq!(second.lines().count(), 2);
+        let decoded: ChatRecord = serde_json::from_str(first.lines().next().unwrap()).unwrap();
+        assert_eq!(decoded.message, "hello\nworld");
+        assert_eq!(decoded.mode, Some(TargetMode::Channel));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = ChatLogger::new(dir.path().to_path_buf());
+        let record: ChatRecord = serde_json::from_str(
+            r#"{"timestamp":"2024-01-01T00:00:00+00:00","schandlerid":1,"server":"Test","mode":null,"sender":"Alice","sender_uid":"uid=","outgoing":false,"message":"hi"}"#,
+        )
+        .unwrap();
+        // Record written before server uid was recorded
+        assert_eq!(record.server_uid, "");
+        logger.write(&record).unwrap();
+
+        let path = dir.path().join("unknown").join("2024-01-01.jsonl");
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,265 @@
+mod inner {
+    use crate::datastructures::{ClientAction, ClientSelector, MessageTarget, TransmissionCommand};
+    use anyhow::anyhow;
+    use std::collections::BTreeMap;
+
+    pub type CommandHandler =
+        Box<dyn Fn(&str) -> anyhow::Result<Option<TransmissionCommand>> + Send>;
+
+    struct Command {
+        usage: &'static str,
+        description: &'static str,
+        handler: CommandHandler,
+    }
+
+    /// Dispatch lines start with `/` to named handlers, other lines are sent as plain text.
+    ///
+    /// `//` escapes a literal slash, `/help` is built-in.
+    #[derive(Default)]
+    pub struct CommandRegistry {
+        commands: BTreeMap<&'static str, Command>,
+        aliases: BTreeMap<String, String>,
+    }
+
+    impl CommandRegistry {
+        /// Register a handler, handler receives trimmed arguments after command name.
+        pub fn register<F>(
+            &mut self,
+            name: &'static str,
+            usage: &'static str,
+            description: &'static str,
+            handler: F,
+        ) -> &mut Self
+        where
+            F: Fn(&str) -> anyhow::Result<Option<TransmissionCommand>> + Send + 'static,
+        {
+            self.commands.insert(
+                name,
+                Command {
+                    usage,
+                    description,
+                    handler: Box::new(handler),
+                },
+            );
+            self
+        }
+
+        /// `/name args` is dispatched as `expansion args`, registered commands take precedence.
+        pub fn alias(&mut self, name: String, expansion: String) -> &mut Self {
+            self.aliases.insert(name, expansion);
+            self
+        }
+
+        /// Names of commands and aliases, without slash.
+        pub fn names(&self) -> Vec<String> {
+            std::iter::once("help".to_string())
+                .chain(self.commands.keys().map(|name| name.to_string()))
+                .chain(self.aliases.keys().cloned())
+                .collect()
+        }
+
+        pub fn help(&self) -> String {
+            let mut lines = vec![
+                "/help - Show this help".to_string(),
+                "//<text> - Send text starts with a slash".to_string(),
+            ];
+            lines.extend(
+                self.commands
+                    .values()
+                    .map(|command| format!("{} - {}", command.usage, command.description)),
+            );
+            lines.extend(
+                self.aliases
+                    .iter()
+                    .map(|(name, expansion)| format!("/{} - Alias of {}", name, expansion)),
+            );
+            lines.join("\n")
+        }
+
+        pub fn dispatch(&self, line: &str) -> anyhow::Result<Option<TransmissionCommand>> {
+            self.dispatch_line(line, true)
+        }
+
+        /// Alias is expanded only once, so alias cannot refer to other alias.
+        fn dispatch_line(
+            &self,
+            line: &str,
+            expand: bool,
+        ) -> anyhow::Result<Option<TransmissionCommand>> {
+            if line.starts_with("//") {
+                return Ok(Some(TransmissionCommand::Data(line[1..].to_string())));
+            }
+            let Some(line) = line.strip_prefix('/') else {
+                return Ok(Some(TransmissionCommand::Data(line.to_string())));
+            };
+
+            let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if name.eq("help") {
+                println!("{}", self.help());
+                return Ok(None);
+            }
+            if let Some(command) = self.commands.get(name) {
+                return (command.handler)(args.trim());
+            }
+            match self.aliases.get(name) {
+                Some(expansion) if expand => {
+                    let line = format!("{} {}", expansion, args.trim());
+                    self.dispatch_line(line.trim_end(), false)
+                }
+                _ => Err(anyhow!("Unknown command: /{}, type /help for help", name)),
+            }
+        }
+
+        /// Send text to target if text is not empty, otherwise switch current target.
+        fn target_command(target: MessageTarget, text: &str) -> Option<TransmissionCommand> {
+            Some(if text.is_empty() {
+                TransmissionCommand::SetTarget(target)
+            } else {
+                TransmissionCommand::Message(target, text.to_string())
+            })
+        }
+
+        fn action(action: ClientAction) -> Option<TransmissionCommand> {
+            Some(TransmissionCommand::Action(action))
+        }
+
+        pub fn builtin() -> Self {
+            let mut registry = Self::default();
+            registry
+                .register(
+                    "msg",
+                    "/msg <nickname|clid> [text]",
+                    "Send private message, or switch current target if text is empty",
+                    |args| {
+                        let (who, text) =
+                            args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                        if who.is_empty() {
+                            return Err(anyhow!("Usage: /msg <nickname|clid> [text]"));
+                        }
+                        Ok(Self::target_command(
+                            MessageTarget::Client(ClientSelector::from(who)),
+                            text.trim(),
+                        ))
+                    },
+                )
+                .register(
+                    "server",
+                    "/server [text]",
+                    "Send server message, or switch current target if text is empty",
+                    |args| Ok(Self::target_command(MessageTarget::Server, args)),
+                )
+                .register(
+                    "channel",
+                    "/channel [text]",
+                    "Send channel message, or switch current target if text is empty",
+                    |args| Ok(Self::target_command(MessageTarget::Channel, args)),
+                )
+                .register(
+                    "tab",
+                    "/tab [schandlerid|auto]",
+                    "List server tabs, pin to a server tab, or follow current tab",
+                    |args| {
+                        Ok(Some(match args {
+                            "" => TransmissionCommand::ListTabs,
+                            "auto" => TransmissionCommand::SelectTab(None),
+                            _ => TransmissionCommand::SelectTab(Some(
+                                args.parse()
+                                    .map_err(|_| anyhow!("Usage: /tab [schandlerid|auto]"))?,
+                            )),
+                        }))
+                    },
+                )
+                .register("who", "/who", "List clients in your channel", |_| {
+                    Ok(Some(TransmissionCommand::ListClients))
+                })
+                .register(
+                    "channels",
+                    "/channels",
+                    "Show channel tree with clients",
+                    |_| Ok(Some(TransmissionCommand::ListChannels)),
+                )
+                .register(
+                    "join",
+                    "/join <channel|cid>",
+                    "Move yourself to channel",
+                    |args| {
+                        if args.is_empty() {
+                            return Err(anyhow!("Usage: /join <channel|cid>"));
+                        }
+                        Ok(Self::action(ClientAction::Join(args.to_string())))
+                    },
+                )
+                .register("nick", "/nick <name>", "Change your nickname", |args| {
+                    if args.is_empty() {
+                        return Err(anyhow!("Usage: /nick <name>"));
+                    }
+                    Ok(Self::action(ClientAction::Nick(args.to_string())))
+                })
+                .register(
+                    "away",
+                    "/away [message]",
+                    "Set away with message, or toggle away status if message is empty",
+                    |args| {
+                        Ok(Self::action(ClientAction::Away(
+                            (!args.is_empty()).then(|| args.to_string()),
+                        )))
+                    },
+                )
+                .register("mute", "/mute", "Toggle microphone mute", |_| {
+                    Ok(Self::action(ClientAction::Mute))
+                })
+                .register("deafen", "/deafen", "Toggle speaker mute", |_| {
+                    Ok(Self::action(ClientAction::Deafen))
+                })
+                .register(
+                    "poke",
+                    "/poke <nickname|clid> [message]",
+                    "Poke client",
+                    |args| {
+                        let (who, text) =
+                            args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                        if who.is_empty() {
+                            return Err(anyhow!("Usage: /poke <nickname|clid> [message]"));
+                        }
+                        Ok(Self::action(ClientAction::Poke(
+                            ClientSelector::from(who),
+                            text.trim().to_string(),
+                        )))
+                    },
+                );
+            registry
+        }
+    }
+}
+
+pub use inner::CommandRegistry;
+
+#[cfg(test)]
+mod test {
+    use super::CommandRegistry;
+    use crate::datastructures::{ClientSelector, MessageTarget, TransmissionCommand};
+
+    #[test]
+    fn test_alias() {
+        let mut registry = CommandRegistry::builtin();
+        registry
+            .alias("bob".to_string(), "/msg Bob".to_string())
+            .alias("loop".to_string(), "/loop".to_string());
+
+        match registry.dispatch("/bob hello there").unwrap() {
+            Some(TransmissionCommand::Message(
+                MessageTarget::Client(ClientSelector::Nickname(name)),
+                text,
+            )) => {
+                assert_eq!(name, "Bob");
+                assert_eq!(text, "hello there");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            registry.dispatch("/bob").unwrap(),
+            Some(TransmissionCommand::SetTarget(MessageTarget::Client(_)))
+        ));
+        assert!(registry.dispatch("/loop").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+mod inner {
+    use rustyline::completion::{Completer, Pair};
+    use rustyline::highlight::Highlighter;
+    use rustyline::hint::Hinter;
+    use rustyline::validate::Validator;
+    use rustyline::{Context, Helper};
+    use std::sync::{Arc, RwLock};
+
+    #[derive(Debug, Default)]
+    struct CacheData {
+        nicknames: Vec<String>,
+        channels: Vec<String>,
+    }
+
+    /// Nicknames and channel names of active server tab, shared with async side.
+    #[derive(Clone, Debug, Default)]
+    pub struct CompletionCache {
+        inner: Arc<RwLock<CacheData>>,
+    }
+
+    impl CompletionCache {
+        pub fn set(&self, mut nicknames: Vec<String>, mut channels: Vec<String>) {
+            nicknames.sort_unstable_by_key(|name| name.to_lowercase());
+            nicknames.dedup();
+            channels.sort_unstable_by_key(|name| name.to_lowercase());
+            channels.dedup();
+            *self.inner.write().unwrap() = CacheData {
+                nicknames,
+                channels,
+            };
+        }
+    }
+
+    /// Complete slash commands, `/msg` and `/poke` targets, `/join` channels,
+    /// `@nick` mentions and `#channel` names.
+    pub struct InputHelper {
+        cache: CompletionCache,
+        /// Command names without slash
+        commands: Vec<String>,
+    }
+
+    impl InputHelper {
+        pub fn new(cache: CompletionCache, mut commands: Vec<String>) -> Self {
+            commands.sort_unstable();
+            Self { cache, commands }
+        }
+
+        fn matches<'a>(
+            names: impl IntoIterator<Item = &'a String>,
+            prefix: &str,
+            decorate: &str,
+        ) -> Vec<String> {
+            let prefix = prefix.to_lowercase();
+            names
+                .into_iter()
+                .filter(|name| name.to_lowercase().starts_with(&prefix))
+                .map(|name| format!("{}{}", decorate, name))
+                .collect()
+        }
+
+        /// Return start position of replaced word and candidates.
+        pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+            let before = &line[..pos];
+            let start = before
+                .char_indices()
+                .rev()
+                .find(|(_, c)| c.is_whitespace())
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            let word = &before[start..];
+            let cache = self.cache.inner.read().unwrap();
+
+            let candidates = if start == 0 && word.starts_with('/') && !word.starts_with("//") {
+                Self::matches(&self.commands, &word[1..], "/")
+            } else if let Some(prefix) = word.strip_prefix('@') {
+                Self::matches(&cache.nicknames, prefix, "@")
+            } else if let Some(prefix) = word.strip_prefix('#') {
+                Self::matches(&cache.channels, prefix, "#")
+            } else if matches!(before[..start].trim_end(), "/msg" | "/poke") {
+                // Target ends at whitespace
+                Self::matches(
+                    cache.nicknames.iter().filter(|name| !name.contains(' ')),
+                    word,
+                    "",
+                )
+            } else if before[..start].trim_end().eq("/join") {
+                Self::matches(&cache.channels, word, "")
+            } else {
+                Vec::new()
+            };
+            (start, candidates)
+        }
+    }
+
+    impl Completer for InputHelper {
+        type Candidate = Pair;
+
+        fn complete(
+            &self,
+            line: &str,
+            pos: usize,
+            _ctx: &Context<'_>,
+        ) -> rustyline::Result<(usize, Vec<Pair>)> {
+            let (start, candidates) = self.candidates(line, pos);
+            Ok((
+                start,
+                candidates
+                    .into_iter()
+                    .map(|candidate| Pair {
+                        replacement: format!("{} ", candidate),
+                        display: candidate,
+                    })
+                    .collect(),
+            ))
+        }
+    }
+
+    impl Hinter for InputHelper {
+        type Hint = String;
+    }
+
+    impl Highlighter for InputHelper {}
+
+    impl Validator for InputHelper {}
+
+    impl Helper for InputHelper {}
+}
+
+pub use inner::{CompletionCache, InputHelper};
+
+#[cfg(test)]
+mod test {
+    use super::{CompletionCache, InputHelper};
+
+    #[test]
+    fn test_candidates() {
+        let cache = CompletionCache::default();
+        cache.set(
+            vec!["alice".to_string(), "Bob".to_string(), "Alan".to_string()],
+            vec!["Lobby".to_string(), "AFK".to_string()],
+        );
+        let helper = InputHelper::new(
+            cache,
+            vec!["msg".to_string(), "ml".to_string(), "tab".to_string()],
+        );
+
+        assert_eq!(
+            helper.candidates("/m", 2),
+            (0, vec!["/ml".into(), "/msg".into()])
+        );
+        assert_eq!(
+            helper.candidates("/msg a", 6),
+            (5, vec!["Alan".into(), "alice".into()])
+        );
+        assert_eq!(helper.candidates("hi @b", 5), (3, vec!["@Bob".into()]));
+        assert_eq!(
+            helper.candidates("see #lo now", 7),
+            (4, vec!["#Lobby".into()])
+        );
+        assert_eq!(
+            helper.candidates("/msg Bob hi a", 13).1,
+            Vec::<String>::new()
+        );
+        assert_eq!(helper.candidates("//m", 3).1, Vec::<String>::new());
+        assert_eq!(helper.candidates("/join a", 7), (6, vec!["AFK".into()]));
+        assert_eq!(helper.candidates("/poke b", 7), (6, vec!["Bob".into()]));
+    }
+}
@@ -0,0 +1,198 @@
+mod inner {
+    use anyhow::anyhow;
+    use chrono::format::{Item, StrftimeItems};
+    use serde_derive::Deserialize;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+
+    /// Settings of config file, every field is optional so profile can override part of it.
+    #[derive(Clone, Debug, Default, Deserialize)]
+    #[serde(default, rename_all = "kebab-case")]
+    pub struct Profile {
+        /// Config file should only be accessible by owner if this is set
+        pub api_key: Option<String>,
+        /// File contains API key, should only be accessible by owner
+        pub api_key_file: Option<PathBuf>,
+        pub server: Option<String>,
+        pub port: Option<u16>,
+        pub schandlerid: Option<i64>,
+        /// Seconds without transmission before keep alive is sent
+        pub keepalive_interval: Option<u64>,
+        /// Capacity of channel between input thread and connection
+        pub command_queue_size: Option<usize>,
+        /// `channel`, `server` or `client:<nickname|clid>`
+        pub default_target: Option<String>,
+        pub events: Option<Vec<String>>,
+        /// strftime format of timestamp
+        pub time_format: Option<String>,
+        /// Template of message line, see `format::Template`
+        pub message_format: Option<String>,
+        pub hide_own_messages: Option<bool>,
+        pub split_markers: Option<bool>,
+        pub burst: Option<u32>,
+        pub rate: Option<f64>,
+        pub history_size: Option<usize>,
+        pub history_ignore_space: Option<bool>,
+        /// Write chat log to this directory
+        pub log_dir: Option<PathBuf>,
+        /// `/name args` is expanded to `value args`
+        pub aliases: BTreeMap<String, String>,
+    }
+
+    impl Profile {
+        /// Fields set in `other` take precedence.
+        fn merge(mut self, other: Profile) -> Self {
+            macro_rules! merge {
+                ($($field:ident),*) => {
+                    $(if other.$field.is_some() {
+                        self.$field = other.$field;
+                    })*
+                };
+            }
+            merge!(
+                api_key,
+                api_key_file,
+                server,
+                port,
+                schandlerid,
+                keepalive_interval,
+                command_queue_size,
+                default_target,
+                events,
+                time_format,
+                message_format,
+                hide_own_messages,
+                split_markers,
+                burst,
+                rate,
+                history_size,
+                history_ignore_space,
+                log_dir
+            );
+            self.aliases.extend(other.aliases);
+            self
+        }
+
+        /// Reject values which would fail later at runtime.
+        fn validate(self) -> anyhow::Result<Self> {
+            if self.command_queue_size == Some(0) {
+                return Err(anyhow!("command-queue-size should be at least 1"));
+            }
+            if let Some(format) = &self.time_format {
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    return Err(anyhow!("Invalid time-format {:?}", format));
+                }
+            }
+            Ok(self)
+        }
+    }
+
+    /// Top level settings are shared by all profiles, `[profile.<name>]` overrides them.
+    #[derive(Clone, Debug, Default, Deserialize)]
+    #[serde(default)]
+    pub struct ConfigFile {
+        #[serde(flatten)]
+        base: Profile,
+        profile: BTreeMap<String, Profile>,
+    }
+
+    impl ConfigFile {
+        /// `$XDG_CONFIG_HOME/teamspeak-input-helper/config.toml`
+        pub fn default_path() -> Option<PathBuf> {
+            dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+        }
+
+        pub fn parse(s: &str) -> anyhow::Result<Self> {
+            toml::from_str(s).map_err(|e| anyhow!("Unable parse config file: {}", e))
+        }
+
+        pub fn load(path: &Path) -> anyhow::Result<Self> {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("Unable read config file {}: {}", path.display(), e))?;
+            Self::parse(&content)
+        }
+
+        /// Load specified file, or default file if it exists.
+        pub fn load_or_default(path: Option<&Path>) -> anyhow::Result<Self> {
+            match path {
+                Some(path) => Self::load(path),
+                None => match Self::default_path() {
+                    Some(path) if path.exists() => Self::load(&path),
+                    _ => Ok(Self::default()),
+                },
+            }
+        }
+
+        pub fn profile(&self, name: Option<&str>) -> anyhow::Result<Profile> {
+            let Some(name) = name else {
+                return self.base.clone().validate();
+            };
+            let profile = self
+                .profile
+                .get(name)
+                .ok_or_else(|| anyhow!("Profile {:?} not found in config file", name))?;
+            self.base.clone().merge(profile.clone()).validate()
+        }
+    }
+}
+
+pub use inner::ConfigFile;
+
+#[cfg(test)]
+mod test {
+    use super::ConfigFile;
+
+    #[test]
+    fn test_profile_override() {
+        let config = ConfigFile::parse(
+            r#"
+            api-key = "AAAA"
+            port = 25639
+            events = ["poke"]
+
+            [aliases]
+            bob = "/msg Bob"
+
+            [profile.work]
+            server = "10.0.0.2"
+            events = []
+
+            [profile.work.aliases]
+            all = "/server"
+            "#,
+        )
+        .unwrap();
+
+        let base = config.profile(None).unwrap();
+        assert_eq!(base.server, None);
+        assert_eq!(base.events, Some(vec!["poke".to_string()]));
+
+        let work = config.profile(Some("work")).unwrap();
+        assert_eq!(work.api_key.as_deref(), Some("AAAA"));
+        assert_eq!(work.server.as_deref(), Some("10.0.0.2"));
+        assert_eq!(work.port, Some(25639));
+        assert_eq!(work.events, Some(vec![]));
+        assert_eq!(work.aliases.len(), 2);
+
+        assert!(config.profile(Some("home")).is_err());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let config = ConfigFile::parse(
+            r#"
+            time-format = "%H:%M"
+
+            [profile.zero]
+            command-queue-size = 0
+
+            [profile.time]
+            time-format = "%Y-%Q"
+            "#,
+        )
+        .unwrap();
+        assert!(config.profile(None).is_ok());
+        assert!(config.profile(Some("zero")).is_err());
+        assert!(config.profile(Some("time")).is_err());
+    }
+}
@@ -3,9 +3,38 @@ pub trait FromQueryString: for<'de> Deserialize<'de> {
     where
         Self: Sized,
     {
-        serde_teamspeak_querystring::from_str(data)
+        serde_teamspeak_querystring::from_str(&crate::escape::normalize(data))
             .map_err(|e| anyhow::anyhow!("Got parser error: {:?}", e))
     }
+
+    /// Decode `|` separated records, e.g. `notifyclientmoved ctid=2 clid=3|clid=4`.
+    ///
+    /// Fields of first record are shared by following records unless they are overridden.
+    fn from_query_records(data: &str) -> Vec<anyhow::Result<Self>>
+    where
+        Self: Sized,
+    {
+        let key = |field: &str| {
+            field
+                .split_once('=')
+                .map_or(field, |(key, _)| key)
+                .to_string()
+        };
+        let mut records = data.split('|');
+        let first = records.next().unwrap_or_default();
+        std::iter::once(first.to_string())
+            .chain(records.map(|record| {
+                let keys = record.split(' ').map(key).collect::<Vec<_>>();
+                first
+                    .split(' ')
+                    .filter(|field| !keys.contains(&key(field)))
+                    .chain(record.split(' '))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }))
+            .map(|record| Self::from_query(&record))
+            .collect()
+    }
 }
 
 impl FromQueryString for () {
@@ -42,33 +71,902 @@ mod schandler_id {
     impl FromQueryString for SchandlerId {}
 }
 
-mod notifies {
+mod who_am_i {
+    use crate::datastructures::FromQueryString;
+    use serde_derive::Deserialize;
+
+    #[derive(Copy, Clone, Debug, Deserialize)]
+    pub struct WhoAmI {
+        clid: i64,
+    }
+
+    impl WhoAmI {
+        pub fn client_id(&self) -> i64 {
+            self.clid
+        }
+    }
+
+    impl FromQueryString for WhoAmI {}
+}
+
+mod server_info {
+    use crate::datastructures::FromQueryString;
+    use serde_derive::Deserialize;
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct ServerName {
+        #[serde(rename = "virtualserver_name")]
+        name: String,
+        #[serde(rename = "virtualserver_unique_identifier", default)]
+        unique_id: String,
+    }
+
+    impl ServerName {
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+        /// Unique identifier of virtual server, same across connections and tabs.
+        pub fn unique_id(&self) -> &str {
+            &self.unique_id
+        }
+    }
+
+    impl FromQueryString for ServerName {}
+}
+
+mod client {
     use crate::datastructures::FromQueryString;
     use serde_derive::Deserialize;
 
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct Client {
+        clid: i64,
+        #[serde(default)]
+        cid: i64,
+        client_nickname: String,
+        #[serde(default)]
+        client_type: i64,
+        /// Fields below are only returned with `-uid -away -voice`
+        #[serde(default)]
+        client_unique_identifier: String,
+        #[serde(default)]
+        client_away: bool,
+        #[serde(default)]
+        client_away_message: String,
+        #[serde(default)]
+        client_input_muted: bool,
+        #[serde(default)]
+        client_output_muted: bool,
+        #[serde(default)]
+        client_flag_talking: bool,
+    }
+
+    impl Client {
+        pub fn client_id(&self) -> i64 {
+            self.clid
+        }
+        pub fn channel_id(&self) -> i64 {
+            self.cid
+        }
+        pub fn client_nickname(&self) -> &str {
+            &self.client_nickname
+        }
+        pub fn is_client(&self) -> bool {
+            self.client_type == 0
+        }
+        pub fn unique_identifier(&self) -> &str {
+            &self.client_unique_identifier
+        }
+        pub fn is_away(&self) -> bool {
+            self.client_away
+        }
+        pub fn away_message(&self) -> &str {
+            &self.client_away_message
+        }
+        pub fn is_input_muted(&self) -> bool {
+            self.client_input_muted
+        }
+        pub fn is_output_muted(&self) -> bool {
+            self.client_output_muted
+        }
+        pub fn is_talking(&self) -> bool {
+            self.client_flag_talking
+        }
+    }
+
+    impl FromQueryString for Client {}
+}
+
+mod channel {
+    use crate::datastructures::FromQueryString;
+    use serde_derive::Deserialize;
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct Channel {
+        cid: i64,
+        #[serde(default)]
+        pid: i64,
+        /// Channel id of previous sibling, 0 if first
+        #[serde(default)]
+        channel_order: i64,
+        channel_name: String,
+    }
+
+    impl Channel {
+        pub fn channel_id(&self) -> i64 {
+            self.cid
+        }
+        pub fn parent_id(&self) -> i64 {
+            self.pid
+        }
+        pub fn order(&self) -> i64 {
+            self.channel_order
+        }
+        pub fn channel_name(&self) -> &str {
+            &self.channel_name
+        }
+    }
+
+    impl FromQueryString for Channel {}
+}
+
+mod notifies {
+    use crate::datastructures::{FromQueryString, TargetMode};
+    use serde_derive::Deserialize;
+
     #[derive(Clone, Debug, Deserialize)]
     pub struct NotifyTextMessage {
-        /*#[serde(rename = "targetmode", default)]
-        target_mode: i8,*/
+        #[serde(rename = "schandlerid", default)]
+        server_id: i64,
+        #[serde(rename = "targetmode", default)]
+        target_mode: i8,
         msg: String,
-        /*#[serde(rename = "invokerid", default)]
-        invoker_id: i64,*/
+        #[serde(rename = "invokerid", default)]
+        invoker_id: i64,
         #[serde(rename = "invokername", default)]
         invoker_name: String,
-        /*#[serde(rename = "invokeruid", default)]
-        invoker_uid: String,*/
+        #[serde(rename = "invokeruid", default)]
+        invoker_uid: String,
     }
 
     impl NotifyTextMessage {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn target_mode(&self) -> Option<TargetMode> {
+            TargetMode::from_code(self.target_mode)
+        }
         pub fn msg(&self) -> &str {
             &self.msg
         }
+        pub fn invoker_id(&self) -> i64 {
+            self.invoker_id
+        }
         pub fn invoker_name(&self) -> &str {
             &self.invoker_name
         }
+        pub fn invoker_uid(&self) -> &str {
+            &self.invoker_uid
+        }
     }
 
     impl FromQueryString for NotifyTextMessage {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyCurrentServerConnectionChanged {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+    }
+
+    impl NotifyCurrentServerConnectionChanged {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+    }
+
+    impl FromQueryString for NotifyCurrentServerConnectionChanged {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyConnectStatusChange {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        status: String,
+    }
+
+    impl NotifyConnectStatusChange {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn status(&self) -> &str {
+            &self.status
+        }
+        pub fn is_established(&self) -> bool {
+            self.status.eq("connection_established")
+        }
+        pub fn is_disconnected(&self) -> bool {
+            self.status.eq("disconnected")
+        }
+    }
+
+    impl FromQueryString for NotifyConnectStatusChange {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyClientEnterView {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "clid")]
+        client_id: i64,
+        #[serde(rename = "ctid")]
+        channel_id: i64,
+        #[serde(rename = "client_nickname", default)]
+        nickname: String,
+        #[serde(rename = "client_type", default)]
+        client_type: i64,
+        #[serde(rename = "client_unique_identifier", default)]
+        uid: String,
+        #[serde(rename = "client_away", default)]
+        away: bool,
+        #[serde(rename = "client_away_message", default)]
+        away_message: String,
+        #[serde(rename = "client_input_muted", default)]
+        input_muted: bool,
+        #[serde(rename = "client_output_muted", default)]
+        output_muted: bool,
+    }
+
+    impl NotifyClientEnterView {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn client_id(&self) -> i64 {
+            self.client_id
+        }
+        pub fn channel_id(&self) -> i64 {
+            self.channel_id
+        }
+        pub fn nickname(&self) -> &str {
+            &self.nickname
+        }
+        pub fn is_client(&self) -> bool {
+            self.client_type == 0
+        }
+        pub fn uid(&self) -> &str {
+            &self.uid
+        }
+        pub fn is_away(&self) -> bool {
+            self.away
+        }
+        pub fn away_message(&self) -> &str {
+            &self.away_message
+        }
+        pub fn is_input_muted(&self) -> bool {
+            self.input_muted
+        }
+        pub fn is_output_muted(&self) -> bool {
+            self.output_muted
+        }
+    }
+
+    impl FromQueryString for NotifyClientEnterView {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyClientLeftView {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "clid")]
+        client_id: i64,
+        #[serde(rename = "reasonid", default)]
+        reason_id: i64,
+        #[serde(rename = "reasonmsg", default)]
+        reason_msg: String,
+        #[serde(rename = "invokername", default)]
+        invoker_name: String,
+    }
+
+    impl NotifyClientLeftView {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn client_id(&self) -> i64 {
+            self.client_id
+        }
+        pub fn reason_id(&self) -> i64 {
+            self.reason_id
+        }
+        pub fn reason_msg(&self) -> &str {
+            &self.reason_msg
+        }
+        pub fn invoker_name(&self) -> &str {
+            &self.invoker_name
+        }
+    }
+
+    impl FromQueryString for NotifyClientLeftView {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyClientMoved {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "clid")]
+        client_id: i64,
+        #[serde(rename = "ctid")]
+        channel_id: i64,
+        #[serde(rename = "reasonid", default)]
+        reason_id: i64,
+        #[serde(rename = "reasonmsg", default)]
+        reason_msg: String,
+        #[serde(rename = "invokername", default)]
+        invoker_name: String,
+    }
+
+    impl NotifyClientMoved {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn client_id(&self) -> i64 {
+            self.client_id
+        }
+        pub fn channel_id(&self) -> i64 {
+            self.channel_id
+        }
+        pub fn reason_id(&self) -> i64 {
+            self.reason_id
+        }
+        pub fn reason_msg(&self) -> &str {
+            &self.reason_msg
+        }
+        pub fn invoker_name(&self) -> &str {
+            &self.invoker_name
+        }
+    }
+
+    impl FromQueryString for NotifyClientMoved {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyClientPoke {
+        #[serde(default)]
+        msg: String,
+        #[serde(rename = "invokername", default)]
+        invoker_name: String,
+    }
+
+    impl NotifyClientPoke {
+        pub fn msg(&self) -> &str {
+            &self.msg
+        }
+        pub fn invoker_name(&self) -> &str {
+            &self.invoker_name
+        }
+    }
+
+    impl FromQueryString for NotifyClientPoke {}
+
+    /// Only changed properties are sent.
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyClientUpdated {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "clid")]
+        client_id: i64,
+        #[serde(rename = "client_nickname")]
+        nickname: Option<String>,
+        #[serde(rename = "client_away")]
+        away: Option<bool>,
+        #[serde(rename = "client_away_message")]
+        away_message: Option<String>,
+        #[serde(rename = "client_input_muted")]
+        input_muted: Option<bool>,
+        #[serde(rename = "client_output_muted")]
+        output_muted: Option<bool>,
+    }
+
+    impl NotifyClientUpdated {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn client_id(&self) -> i64 {
+            self.client_id
+        }
+        pub fn nickname(&self) -> Option<&str> {
+            self.nickname.as_deref()
+        }
+        pub fn away(&self) -> Option<bool> {
+            self.away
+        }
+        pub fn away_message(&self) -> Option<&str> {
+            self.away_message.as_deref()
+        }
+        pub fn input_muted(&self) -> Option<bool> {
+            self.input_muted
+        }
+        pub fn output_muted(&self) -> Option<bool> {
+            self.output_muted
+        }
+    }
+
+    impl FromQueryString for NotifyClientUpdated {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyTalkStatusChange {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "clid")]
+        client_id: i64,
+        status: i64,
+    }
+
+    impl NotifyTalkStatusChange {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn client_id(&self) -> i64 {
+            self.client_id
+        }
+        pub fn is_talking(&self) -> bool {
+            self.status == 1
+        }
+    }
+
+    impl FromQueryString for NotifyTalkStatusChange {}
+
+    /// Used by `notifychannelcreated`, `notifychanneledited` and `notifychannelmoved`,
+    /// properties not changed are `None`.
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyChannelChanged {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "cid")]
+        channel_id: i64,
+        #[serde(rename = "cpid")]
+        parent_id: Option<i64>,
+        #[serde(alias = "order")]
+        channel_order: Option<i64>,
+        channel_name: Option<String>,
+    }
+
+    impl NotifyChannelChanged {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn channel_id(&self) -> i64 {
+            self.channel_id
+        }
+        pub fn parent_id(&self) -> Option<i64> {
+            self.parent_id
+        }
+        pub fn order(&self) -> Option<i64> {
+            self.channel_order
+        }
+        pub fn channel_name(&self) -> Option<&str> {
+            self.channel_name.as_deref()
+        }
+    }
+
+    impl FromQueryString for NotifyChannelChanged {}
+
+    #[derive(Clone, Debug, Deserialize)]
+    pub struct NotifyChannelDeleted {
+        #[serde(rename = "schandlerid")]
+        server_id: i64,
+        #[serde(rename = "cid")]
+        channel_id: i64,
+    }
+
+    impl NotifyChannelDeleted {
+        pub fn server_id(&self) -> i64 {
+            self.server_id
+        }
+        pub fn channel_id(&self) -> i64 {
+            self.channel_id
+        }
+    }
+
+    impl FromQueryString for NotifyChannelDeleted {}
+}
+
+mod server_model {
+    use crate::datastructures::{
+        Channel, Client, NotifyChannelChanged, NotifyClientEnterView, NotifyClientUpdated,
+        QueryError, QueryResult,
+    };
+    use std::collections::{BTreeMap, HashMap};
+
+    /// Find client by nickname, exact match is preferred over case insensitive match.
+    ///
+    /// Caller should filter out ServerQuery clients, so both kinds of match see same clients.
+    pub fn find_by_nickname<'a, T>(
+        clients: impl Iterator<Item = &'a T>,
+        nickname: &str,
+        nickname_of: impl Fn(&T) -> &str,
+    ) -> QueryResult<&'a T> {
+        let clients = clients.collect::<Vec<_>>();
+        if let Some(client) = clients.iter().find(|c| nickname_of(c).eq(nickname)) {
+            return Ok(client);
+        }
+        let mut matches = clients
+            .into_iter()
+            .filter(|c| nickname_of(c).eq_ignore_ascii_case(nickname));
+        match (matches.next(), matches.next()) {
+            (Some(client), None) => Ok(client),
+            (Some(_), Some(_)) => Err(QueryError::ambiguous_client(nickname)),
+            _ => Err(QueryError::client_not_found(nickname)),
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct ClientInfo {
+        client_id: i64,
+        channel_id: i64,
+        nickname: String,
+        uid: String,
+        is_client: bool,
+        away: bool,
+        away_message: String,
+        input_muted: bool,
+        output_muted: bool,
+        talking: bool,
+    }
+
+    impl ClientInfo {
+        pub fn client_id(&self) -> i64 {
+            self.client_id
+        }
+        pub fn nickname(&self) -> &str {
+            &self.nickname
+        }
+        pub fn uid(&self) -> &str {
+            &self.uid
+        }
+        /// `false` for ServerQuery clients
+        pub fn is_client(&self) -> bool {
+            self.is_client
+        }
+        pub fn is_away(&self) -> bool {
+            self.away
+        }
+        pub fn away_message(&self) -> &str {
+            &self.away_message
+        }
+        pub fn is_input_muted(&self) -> bool {
+            self.input_muted
+        }
+        pub fn is_output_muted(&self) -> bool {
+            self.output_muted
+        }
+        pub fn is_talking(&self) -> bool {
+            self.talking
+        }
+    }
+
+    impl From<&Client> for ClientInfo {
+        fn from(client: &Client) -> Self {
+            Self {
+                client_id: client.client_id(),
+                channel_id: client.channel_id(),
+                nickname: client.client_nickname().to_string(),
+                uid: client.unique_identifier().to_string(),
+                is_client: client.is_client(),
+                away: client.is_away(),
+                away_message: client.away_message().to_string(),
+                input_muted: client.is_input_muted(),
+                output_muted: client.is_output_muted(),
+                talking: client.is_talking(),
+            }
+        }
+    }
+
+    impl From<&NotifyClientEnterView> for ClientInfo {
+        fn from(view: &NotifyClientEnterView) -> Self {
+            Self {
+                client_id: view.client_id(),
+                channel_id: view.channel_id(),
+                nickname: view.nickname().to_string(),
+                uid: view.uid().to_string(),
+                is_client: view.is_client(),
+                away: view.is_away(),
+                away_message: view.away_message().to_string(),
+                input_muted: view.is_input_muted(),
+                output_muted: view.is_output_muted(),
+                talking: false,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct ChannelInfo {
+        channel_id: i64,
+        parent_id: i64,
+        order: i64,
+        name: String,
+    }
+
+    impl ChannelInfo {
+        pub fn channel_id(&self) -> i64 {
+            self.channel_id
+        }
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    impl From<&Channel> for ChannelInfo {
+        fn from(channel: &Channel) -> Self {
+            Self {
+                channel_id: channel.channel_id(),
+                parent_id: channel.parent_id(),
+                order: channel.order(),
+                name: channel.channel_name().to_string(),
+            }
+        }
+    }
+
+    /// Clients and channels of one server tab.
+    #[derive(Debug, Default)]
+    pub struct ServerTables {
+        clients: BTreeMap<i64, ClientInfo>,
+        channels: BTreeMap<i64, ChannelInfo>,
+    }
+
+    impl ServerTables {
+        pub fn client(&self, client_id: i64) -> Option<&ClientInfo> {
+            self.clients.get(&client_id)
+        }
+
+        pub fn clients(&self) -> impl Iterator<Item = &ClientInfo> {
+            self.clients.values()
+        }
+
+        /// Find client by nickname, ServerQuery clients are ignored.
+        pub fn find_client(&self, nickname: &str) -> QueryResult<&ClientInfo> {
+            find_by_nickname(self.clients().filter(|c| c.is_client), nickname, |c| {
+                &c.nickname
+            })
+        }
+
+        /// Clients in channel, sorted by nickname.
+        pub fn clients_in(&self, channel_id: i64) -> Vec<&ClientInfo> {
+            let mut clients = self
+                .clients()
+                .filter(|c| c.channel_id == channel_id)
+                .collect::<Vec<_>>();
+            clients.sort_by_key(|c| c.nickname.to_lowercase());
+            clients
+        }
+
+        pub fn channel(&self, channel_id: i64) -> Option<&ChannelInfo> {
+            self.channels.get(&channel_id)
+        }
+
+        pub fn channels(&self) -> impl Iterator<Item = &ChannelInfo> {
+            self.channels.values()
+        }
+
+        /// Channel which client is in.
+        pub fn channel_of(&self, client_id: i64) -> Option<&ChannelInfo> {
+            self.client(client_id)
+                .and_then(|client| self.channel(client.channel_id))
+        }
+
+        /// Find channel by name, case insensitive.
+        pub fn find_channel(&self, name: &str) -> Option<&ChannelInfo> {
+            self.channels()
+                .find(|c| c.name.eq(name))
+                .or_else(|| self.channels().find(|c| c.name.eq_ignore_ascii_case(name)))
+        }
+
+        /// Sub channels in display order, use 0 for top level channels.
+        pub fn children(&self, parent_id: i64) -> Vec<&ChannelInfo> {
+            let mut rest = self
+                .channels()
+                .filter(|c| c.parent_id == parent_id)
+                .collect::<Vec<_>>();
+            let mut ordered = Vec::with_capacity(rest.len());
+            // `order` is id of previous sibling
+            let mut previous = 0;
+            while let Some(pos) = rest.iter().position(|c| c.order == previous) {
+                let channel = rest.remove(pos);
+                previous = channel.channel_id;
+                ordered.push(channel);
+            }
+            // Broken order should not hide channels
+            ordered.extend(rest);
+            ordered
+        }
+    }
+
+    /// Client and channel tables of every server tab.
+    #[derive(Debug, Default)]
+    pub struct ServerModel {
+        servers: HashMap<i64, ServerTables>,
+        revision: u64,
+    }
+
+    impl ServerModel {
+        pub fn server(&self, server_id: i64) -> Option<&ServerTables> {
+            self.servers.get(&server_id)
+        }
+
+        /// Changed whenever client or channel is added, removed or renamed.
+        pub fn revision(&self) -> u64 {
+            self.revision
+        }
+
+        /// Table which will be changed by caller, it is created if not exists.
+        fn tables(&mut self, server_id: i64) -> &mut ServerTables {
+            self.revision += 1;
+            self.servers.entry(server_id).or_default()
+        }
+
+        /// Known client only, late notification of removed tab should not bring its table back.
+        fn client_mut(&mut self, server_id: i64, client_id: i64) -> Option<&mut ClientInfo> {
+            self.servers
+                .get_mut(&server_id)
+                .and_then(|tables| tables.clients.get_mut(&client_id))
+        }
+
+        pub fn clear(&mut self) {
+            self.revision += 1;
+            self.servers.clear();
+        }
+
+        pub fn remove_server(&mut self, server_id: i64) {
+            self.revision += 1;
+            self.servers.remove(&server_id);
+        }
+
+        /// Replace client table with result of `clientlist`.
+        pub fn set_clients(&mut self, server_id: i64, clients: &[Client]) {
+            self.tables(server_id).clients = clients
+                .iter()
+                .map(|client| (client.client_id(), client.into()))
+                .collect();
+        }
+
+        /// Replace channel table with result of `channellist`.
+        pub fn set_channels(&mut self, server_id: i64, channels: &[Channel]) {
+            self.tables(server_id).channels = channels
+                .iter()
+                .map(|channel| (channel.channel_id(), channel.into()))
+                .collect();
+        }
+
+        pub fn client_entered(&mut self, view: &NotifyClientEnterView) {
+            self.tables(view.server_id())
+                .clients
+                .insert(view.client_id(), view.into());
+        }
+
+        /// Return removed client.
+        pub fn client_left(&mut self, server_id: i64, client_id: i64) -> Option<ClientInfo> {
+            self.revision += 1;
+            self.servers
+                .get_mut(&server_id)
+                .and_then(|tables| tables.clients.remove(&client_id))
+        }
+
+        pub fn client_moved(&mut self, server_id: i64, client_id: i64, channel_id: i64) {
+            if let Some(client) = self.client_mut(server_id, client_id) {
+                client.channel_id = channel_id;
+            }
+        }
+
+        pub fn client_updated(&mut self, view: &NotifyClientUpdated) {
+            let Some(client) = self.client_mut(view.server_id(), view.client_id()) else {
+                return;
+            };
+            let renamed = view.nickname().is_some();
+            if let Some(nickname) = view.nickname() {
+                client.nickname = nickname.to_string();
+            }
+            if let Some(away) = view.away() {
+                client.away = away;
+            }
+            if let Some(message) = view.away_message() {
+                client.away_message = message.to_string();
+            }
+            if let Some(muted) = view.input_muted() {
+                client.input_muted = muted;
+            }
+            if let Some(muted) = view.output_muted() {
+                client.output_muted = muted;
+            }
+            if renamed {
+                self.revision += 1;
+            }
+        }
+
+        pub fn talk_status_changed(&mut self, server_id: i64, client_id: i64, talking: bool) {
+            if let Some(client) = self.client_mut(server_id, client_id) {
+                client.talking = talking;
+            }
+        }
+
+        /// Apply created, edited or moved channel.
+        pub fn channel_changed(&mut self, view: &NotifyChannelChanged) {
+            let channel = self
+                .tables(view.server_id())
+                .channels
+                .entry(view.channel_id())
+                .or_insert_with(|| ChannelInfo {
+                    channel_id: view.channel_id(),
+                    ..Default::default()
+                });
+            if let Some(parent_id) = view.parent_id() {
+                channel.parent_id = parent_id;
+            }
+            if let Some(order) = view.order() {
+                channel.order = order;
+            }
+            if let Some(name) = view.channel_name() {
+                channel.name = name.to_string();
+            }
+        }
+
+        pub fn channel_deleted(&mut self, server_id: i64, channel_id: i64) {
+            self.revision += 1;
+            if let Some(tables) = self.servers.get_mut(&server_id) {
+                tables.channels.remove(&channel_id);
+            }
+        }
+    }
+}
+
+mod event_kind {
+    use anyhow::anyhow;
+    use std::str::FromStr;
+
+    /// Optional events which can be subscribed by user.
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub enum EventKind {
+        EnterView,
+        LeftView,
+        Moved,
+        Poke,
+    }
+
+    impl EventKind {
+        pub const ALL: [EventKind; 4] = [
+            EventKind::EnterView,
+            EventKind::LeftView,
+            EventKind::Moved,
+            EventKind::Poke,
+        ];
+
+        pub fn notify_name(&self) -> &'static str {
+            match self {
+                EventKind::EnterView => "notifycliententerview",
+                EventKind::LeftView => "notifyclientleftview",
+                EventKind::Moved => "notifyclientmoved",
+                EventKind::Poke => "notifyclientpoke",
+            }
+        }
+
+        pub fn short_name(&self) -> &'static str {
+            match self {
+                EventKind::EnterView => "enter",
+                EventKind::LeftView => "left",
+                EventKind::Moved => "moved",
+                EventKind::Poke => "poke",
+            }
+        }
+    }
+
+    impl FromStr for EventKind {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Self::ALL
+                .into_iter()
+                .find(|kind| kind.short_name().eq(s) || kind.notify_name().eq(s))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unknown event {:?}, available: {}",
+                        s,
+                        Self::ALL.map(|kind| kind.short_name()).join(", ")
+                    )
+                })
+        }
+    }
 }
 
 pub mod query_status {
@@ -139,7 +1037,7 @@ pub mod query_status {
             let (_, line) = value
                 .split_once("error ")
                 .ok_or_else(|| anyhow!("Split error: {}", value))?;
-            serde_teamspeak_querystring::from_str(line)
+            serde_teamspeak_querystring::from_str(&crate::escape::normalize(line))
                 .map_err(|e| anyhow!("Got error while parse string: {:?} {:?}", line, e))
         }
     }
@@ -166,10 +1064,30 @@ mod query_result {
             }
         }
 
-        pub fn send_message_error(data: String) -> Self {
+        pub fn code(&self) -> i32 {
+            self.code
+        }
+
+        pub fn is_flooding(&self) -> bool {
+            self.code == 524
+        }
+
+        /// Error produced by connection or flood protection, which may succeed if we try again later.
+        pub fn is_retryable(&self) -> bool {
+            self.code == -2 || self.is_flooding()
+        }
+
+        pub fn client_not_found(selector: &str) -> Self {
             Self {
-                code: -2,
-                message: format!("Unable to send message, raw data => {}", data),
+                code: -4,
+                message: format!("Client not found: {}", selector),
+            }
+        }
+
+        pub fn ambiguous_client(selector: &str) -> Self {
+            Self {
+                code: -5,
+                message: format!("More than one client matches: {}", selector),
             }
         }
 
@@ -208,18 +1126,303 @@ mod query_result {
     }
 }
 
+mod message_target {
+    use anyhow::anyhow;
+    use serde_derive::{Deserialize, Serialize};
+    use std::fmt::{Display, Formatter};
+    use std::str::FromStr;
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub enum ClientSelector {
+        Id(i64),
+        Nickname(String),
+    }
+
+    impl From<&str> for ClientSelector {
+        fn from(s: &str) -> Self {
+            s.parse()
+                .map(Self::Id)
+                .unwrap_or_else(|_| Self::Nickname(s.to_string()))
+        }
+    }
+
+    impl Display for ClientSelector {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ClientSelector::Id(id) => write!(f, "clid={}", id),
+                ClientSelector::Nickname(name) => write!(f, "{}", name),
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TargetMode {
+        Private,
+        Channel,
+        Server,
+    }
+
+    impl TargetMode {
+        pub fn from_code(code: i8) -> Option<Self> {
+            match code {
+                1 => Some(Self::Private),
+                2 => Some(Self::Channel),
+                3 => Some(Self::Server),
+                _ => None,
+            }
+        }
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                TargetMode::Private => "PM",
+                TargetMode::Channel => "CH",
+                TargetMode::Server => "SRV",
+            }
+        }
+    }
+
+    impl FromStr for TargetMode {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_ascii_lowercase().as_str() {
+                "private" | "pm" => Ok(Self::Private),
+                "channel" | "ch" => Ok(Self::Channel),
+                "server" | "srv" => Ok(Self::Server),
+                _ => Err(anyhow!(
+                    "Unknown mode {:?}, expect private, channel or server",
+                    s
+                )),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, Eq, PartialEq)]
+    pub enum MessageTarget {
+        Client(ClientSelector),
+        #[default]
+        Channel,
+        Server,
+    }
+
+    impl Display for MessageTarget {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                MessageTarget::Client(selector) => write!(f, "private message to {}", selector),
+                MessageTarget::Channel => write!(f, "channel"),
+                MessageTarget::Server => write!(f, "server"),
+            }
+        }
+    }
+
+    impl MessageTarget {
+        pub fn mode(&self) -> TargetMode {
+            match self {
+                MessageTarget::Client(_) => TargetMode::Private,
+                MessageTarget::Channel => TargetMode::Channel,
+                MessageTarget::Server => TargetMode::Server,
+            }
+        }
+    }
+
+    impl FromStr for MessageTarget {
+        type Err = anyhow::Error;
+
+        /// Parse `channel`, `server` or `client:<nickname|clid>`.
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "channel" => Ok(Self::Channel),
+                "server" => Ok(Self::Server),
+                _ => s
+                    .strip_prefix("client:")
+                    .filter(|client| !client.is_empty())
+                    .map(|client| Self::Client(ClientSelector::from(client)))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Unknown target {:?}, expect channel, server or client:<nickname|clid>",
+                            s
+                        )
+                    }),
+            }
+        }
+    }
+}
+
 mod transmission_command {
+    use crate::datastructures::{ClientSelector, MessageTarget};
+
+    /// Actions on our own client or other clients, need connection to server.
+    #[derive(Clone, Debug)]
+    pub enum ClientAction {
+        /// Channel name or channel id
+        Join(String),
+        Nick(String),
+        /// Toggle away status if message is `None`, otherwise set away with message
+        Away(Option<String>),
+        /// Toggle microphone
+        Mute,
+        /// Toggle speakers
+        Deafen,
+        Poke(ClientSelector, String),
+    }
+
+    impl ClientAction {
+        pub fn command_name(&self) -> &'static str {
+            match self {
+                ClientAction::Join(_) => "/join",
+                ClientAction::Nick(_) => "/nick",
+                ClientAction::Away(_) => "/away",
+                ClientAction::Mute => "/mute",
+                ClientAction::Deafen => "/deafen",
+                ClientAction::Poke(..) => "/poke",
+            }
+        }
+    }
+
     #[derive(Clone, Debug)]
     pub enum TransmissionCommand {
+        /// Plain line, send to current target
         Data(String),
-        KeepAlive,
+        Message(MessageTarget, String),
+        SetTarget(MessageTarget),
+        /// Pin to specified server tab, or follow current tab if `None`
+        SelectTab(Option<i64>),
+        ListTabs,
+        /// Clients in our channel, fetched from server
+        ListClients,
+        /// Channel tree with clients, fetched from server
+        ListChannels,
+        Action(ClientAction),
         Terminate,
     }
 }
 
-pub use notifies::NotifyTextMessage;
+pub use channel::Channel;
+pub use client::Client;
+pub use event_kind::EventKind;
+pub use message_target::{ClientSelector, MessageTarget, TargetMode};
+pub use notifies::{
+    NotifyChannelChanged, NotifyChannelDeleted, NotifyClientEnterView, NotifyClientLeftView,
+    NotifyClientMoved, NotifyClientPoke, NotifyClientUpdated, NotifyConnectStatusChange,
+    NotifyCurrentServerConnectionChanged, NotifyTalkStatusChange, NotifyTextMessage,
+};
 pub use query_result::{QueryError, QueryResult};
 pub use query_status::QueryStatus;
 pub use schandler_id::SchandlerId;
 use serde::Deserialize;
-pub use transmission_command::TransmissionCommand;
+pub use server_info::ServerName;
+pub use server_model::{find_by_nickname, ClientInfo, ServerModel, ServerTables};
+pub use transmission_command::{ClientAction, TransmissionCommand};
+pub use who_am_i::WhoAmI;
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Channel, Client, FromQueryString, NotifyChannelChanged, NotifyClientEnterView,
+        NotifyClientMoved, NotifyClientUpdated, ServerModel,
+    };
+
+    fn parse<T: FromQueryString>(data: &str) -> Vec<T> {
+        data.split('|').map(|s| T::from_query(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_batched_records() {
+        let records = NotifyClientMoved::from_query_records(
+            r"notifyclientmoved schandlerid=1 ctid=2 reasonid=0 clid=3|clid=4|ctid=5 clid=6|clid=x",
+        );
+        let moved = records
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|view| (view.server_id(), view.client_id(), view.channel_id()))
+            .collect::<Vec<_>>();
+        assert_eq!(moved, [(1, 3, 2), (1, 4, 2), (1, 6, 5)]);
+        assert!(records[3].is_err());
+    }
+
+    #[test]
+    fn test_server_model() {
+        let mut model = ServerModel::default();
+        model.set_clients(
+            1,
+            &parse::<Client>(
+                r"clid=1 cid=1 client_nickname=Alice client_type=0 client_unique_identifier=a= client_away=0 client_away_message client_flag_talking=1 client_input_muted=0 client_output_muted=0|clid=2 cid=2 client_nickname=bob client_type=0 client_unique_identifier=b= client_away=1 client_away_message=brb client_flag_talking=0 client_input_muted=1 client_output_muted=0|clid=3 cid=1 client_nickname=Bob client_type=1",
+            ),
+        );
+        model.set_channels(
+            1,
+            &parse::<Channel>(
+                r"cid=1 pid=0 channel_order=3 channel_name=Lobby|cid=2 pid=1 channel_order=0 channel_name=Sub|cid=3 pid=0 channel_order=0 channel_name=Top",
+            ),
+        );
+        let tables = model.server(1).unwrap();
+        assert!(tables.client(1).unwrap().is_talking());
+        assert_eq!(tables.client(2).unwrap().away_message(), "brb");
+        // ServerQuery client is ignored even if nickname matches exactly
+        assert_eq!(tables.find_client("Bob").unwrap().client_id(), 2);
+        assert!(tables.find_client("carol").is_err());
+        assert_eq!(tables.channel_of(2).unwrap().name(), "Sub");
+        assert_eq!(tables.find_channel("lobby").unwrap().channel_id(), 1);
+        assert_eq!(
+            tables
+                .children(0)
+                .iter()
+                .map(|c| c.name())
+                .collect::<Vec<_>>(),
+            ["Top", "Lobby"]
+        );
+
+        model.client_entered(
+            &NotifyClientEnterView::from_query(
+                r"notifycliententerview schandlerid=1 reasonid=0 ctid=3 clid=7 client_nickname=Carol client_type=0 client_unique_identifier=c=",
+            )
+            .unwrap(),
+        );
+        let revision = model.revision();
+        model.client_moved(1, 1, 3);
+        model.talk_status_changed(1, 1, false);
+        assert_eq!(model.revision(), revision);
+        model.client_updated(
+            &NotifyClientUpdated::from_query(
+                r"notifyclientupdated schandlerid=1 clid=2 client_away=0 client_nickname=Bobby",
+            )
+            .unwrap(),
+        );
+        model.channel_changed(
+            &NotifyChannelChanged::from_query(
+                r"notifychanneledited schandlerid=1 cid=3 reasonid=10 invokerid=1 channel_name=Main",
+            )
+            .unwrap(),
+        );
+        let tables = model.server(1).unwrap();
+        assert_eq!(
+            tables
+                .clients_in(3)
+                .iter()
+                .map(|c| c.nickname())
+                .collect::<Vec<_>>(),
+            ["Alice", "Carol"]
+        );
+        let bob = tables.client(2).unwrap();
+        assert_eq!((bob.nickname(), bob.is_away()), ("Bobby", false));
+        assert_ne!(model.revision(), revision);
+        assert_eq!(tables.channel(3).unwrap().name(), "Main");
+
+        assert_eq!(model.client_left(1, 7).unwrap().uid(), "c=");
+        model.channel_deleted(1, 2);
+        assert!(model.server(1).unwrap().channel_of(2).is_none());
+
+        // Late notifications of disconnected tab are ignored
+        model.remove_server(1);
+        model.client_moved(1, 1, 2);
+        model.talk_status_changed(1, 1, true);
+        model.client_updated(
+            &NotifyClientUpdated::from_query(
+                r"notifyclientupdated schandlerid=1 clid=1 client_away=1",
+            )
+            .unwrap(),
+        );
+        assert!(model.server(1).is_none());
+    }
+}
@@ -0,0 +1,130 @@
+mod inner {
+    use std::borrow::Cow;
+
+    /// Escape table of ClientQuery, (raw character, escaped character after backslash)
+    const ESCAPE_TABLE: [(char, char); 11] = [
+        ('\\', '\\'),
+        ('/', '/'),
+        (' ', 's'),
+        ('|', 'p'),
+        ('\x07', 'a'),
+        ('\x08', 'b'),
+        ('\x0c', 'f'),
+        ('\n', 'n'),
+        ('\r', 'r'),
+        ('\t', 't'),
+        ('\x0b', 'v'),
+    ];
+
+    /// Escape value to be used as a ClientQuery parameter.
+    pub fn escape(s: &str) -> String {
+        let mut ret = String::with_capacity(s.len());
+        for c in s.chars() {
+            match ESCAPE_TABLE.iter().find(|(raw, _)| *raw == c) {
+                Some((_, escaped)) => {
+                    ret.push('\\');
+                    ret.push(*escaped);
+                }
+                None => ret.push(c),
+            }
+        }
+        ret
+    }
+
+    /// Decode escaped ClientQuery value, unknown escape sequence will be kept as is.
+    pub fn unescape(s: &str) -> String {
+        let mut ret = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                ret.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some(next) => match ESCAPE_TABLE.iter().find(|(_, escaped)| *escaped == next) {
+                    Some((raw, _)) => ret.push(*raw),
+                    None => {
+                        ret.push(c);
+                        ret.push(next);
+                    }
+                },
+                None => ret.push(c),
+            }
+        }
+        ret
+    }
+
+    /// Decode every value in the line, then escape them again in the way which
+    /// `serde_teamspeak_querystring` can understand, since it only handle `\\`, `\s` and `\/`.
+    pub fn normalize(line: &str) -> Cow<'_, str> {
+        if !line.contains('\\') {
+            return Cow::Borrowed(line);
+        }
+        Cow::Owned(
+            line.split(' ')
+                .map(|pair| match pair.split_once('=') {
+                    Some((key, value)) => format!(
+                        "{}={}",
+                        key,
+                        unescape(value).replace('\\', "\\\\").replace(' ', "\\s")
+                    ),
+                    None => pair.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+}
+
+pub use inner::{escape, normalize};
+
+#[cfg(test)]
+mod test {
+    use super::inner::unescape;
+    use super::{escape, normalize};
+    use crate::datastructures::{FromQueryString, NotifyTextMessage};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_escape_table() {
+        assert_eq!(
+            escape("a b|c/d\\e\nf\rg\th\x07\x08\x0c\x0b"),
+            "a\\sb\\pc\\/d\\\\e\\nf\\rg\\th\\a\\b\\f\\v"
+        );
+    }
+
+    #[test]
+    fn test_unescape_unknown_sequence() {
+        assert_eq!(unescape("\\x\\"), "\\x\\");
+    }
+
+    #[test]
+    fn test_normalize_keep_escaped_backslash() {
+        assert_eq!(normalize("msg=\\\\n\\n\\s"), "msg=\\\\n\n\\s");
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(s in any::<String>()) {
+            prop_assert_eq!(unescape(&escape(&s)), s);
+        }
+
+        #[test]
+        fn test_escaped_has_no_separator(s in any::<String>()) {
+            let escaped = escape(&s);
+            prop_assert!(!escaped.contains([' ', '|', '\n', '\r']));
+        }
+
+        #[test]
+        fn test_decode_incoming_value(s in any::<String>()) {
+            let line = format!(
+                "notifytextmessage schandlerid=1 targetmode=2 msg={} invokerid=1 invokername={}",
+                escape(&s),
+                escape(&s)
+            );
+            let view = NotifyTextMessage::from_query(&line).unwrap();
+            prop_assert_eq!(view.msg(), s.as_str());
+            prop_assert_eq!(view.invoker_name(), s.as_str());
+        }
+    }
+}
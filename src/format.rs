@@ -0,0 +1,286 @@
+mod inner {
+    use anyhow::anyhow;
+    use std::io::IsTerminal;
+    use std::str::FromStr;
+
+    pub const DEFAULT_TEMPLATE: &str =
+        "[{time}] [{mode}] [{server}] {sender:bold+auto}{own}: {msg}";
+
+    /// Colours picked by `auto` style, black and white are excluded to keep text readable.
+    const AUTO_COLORS: [u8; 12] = [31, 32, 33, 34, 35, 36, 91, 92, 93, 94, 95, 96];
+
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    enum Field {
+        Time,
+        Server,
+        Channel,
+        Sender,
+        Uid,
+        Mode,
+        Msg,
+        /// ` (you)` if message is sent by ourselves
+        Own,
+    }
+
+    impl FromStr for Field {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(match s {
+                "time" => Self::Time,
+                "server" => Self::Server,
+                "channel" => Self::Channel,
+                "sender" => Self::Sender,
+                "uid" => Self::Uid,
+                "mode" => Self::Mode,
+                "msg" => Self::Msg,
+                "own" => Self::Own,
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown placeholder {{{}}}, available: time, server, channel, sender, uid, mode, msg, own",
+                        s
+                    ))
+                }
+            })
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    enum Style {
+        Code(u8),
+        /// Stable colour derived from sender uid
+        Auto,
+    }
+
+    impl FromStr for Style {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(Self::Code(match s {
+                "auto" => return Ok(Self::Auto),
+                "bold" => 1,
+                "dim" => 2,
+                "italic" => 3,
+                "underline" => 4,
+                "black" => 30,
+                "red" => 31,
+                "green" => 32,
+                "yellow" => 33,
+                "blue" => 34,
+                "magenta" => 35,
+                "cyan" => 36,
+                "white" => 37,
+                _ => return Err(anyhow!("Unknown style {:?}", s)),
+            }))
+        }
+    }
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    enum Segment {
+        Text(String),
+        Field(Field, Vec<Style>),
+    }
+
+    /// Values of placeholders.
+    #[derive(Debug, Default)]
+    pub struct MessageFields<'a> {
+        pub time: &'a str,
+        pub server: &'a str,
+        pub channel: &'a str,
+        pub sender: &'a str,
+        pub uid: &'a str,
+        pub mode: &'a str,
+        pub msg: &'a str,
+        pub own: bool,
+    }
+
+    impl MessageFields<'_> {
+        fn get(&self, field: Field) -> &str {
+            match field {
+                Field::Time => self.time,
+                Field::Server => self.server,
+                Field::Channel => self.channel,
+                Field::Sender => self.sender,
+                Field::Uid => self.uid,
+                Field::Mode => self.mode,
+                Field::Msg => self.msg,
+                Field::Own => {
+                    if self.own {
+                        " (you)"
+                    } else {
+                        ""
+                    }
+                }
+            }
+        }
+
+        /// FNV-1a, so colour of sender is same across runs.
+        fn auto_color(&self) -> u8 {
+            let key = if self.uid.is_empty() {
+                self.sender
+            } else {
+                self.uid
+            };
+            let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(0x100000001b3)
+            });
+            AUTO_COLORS[(hash % AUTO_COLORS.len() as u64) as usize]
+        }
+    }
+
+    /// Message line template, e.g. `[{time}] {sender:bold+auto}: {msg}`.
+    ///
+    /// Styles after colon are joined by `+`, `{{` and `}}` are literal braces.
+    #[derive(Clone, Debug)]
+    pub struct Template {
+        segments: Vec<Segment>,
+    }
+
+    impl Default for Template {
+        fn default() -> Self {
+            DEFAULT_TEMPLATE.parse().unwrap()
+        }
+    }
+
+    impl FromStr for Template {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut segments = Vec::new();
+            let mut text = String::new();
+            let mut chars = s.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '{' if chars.peek() == Some(&'{') => {
+                        chars.next();
+                        text.push('{');
+                    }
+                    '}' if chars.peek() == Some(&'}') => {
+                        chars.next();
+                        text.push('}');
+                    }
+                    '{' => {
+                        let mut placeholder = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('}') => break,
+                                Some(c) => placeholder.push(c),
+                                None => return Err(anyhow!("Unclosed placeholder in {:?}", s)),
+                            }
+                        }
+                        let (name, styles) =
+                            placeholder.split_once(':').unwrap_or((&placeholder, ""));
+                        let styles = styles
+                            .split('+')
+                            .filter(|style| !style.is_empty())
+                            .map(Style::from_str)
+                            .collect::<anyhow::Result<Vec<_>>>()?;
+                        if !text.is_empty() {
+                            segments.push(Segment::Text(std::mem::take(&mut text)));
+                        }
+                        segments.push(Segment::Field(name.parse()?, styles));
+                    }
+                    '}' => return Err(anyhow!("Unmatched }} in {:?}, use }}}} for literal", s)),
+                    _ => text.push(c),
+                }
+            }
+            if !text.is_empty() {
+                segments.push(Segment::Text(text));
+            }
+            Ok(Self { segments })
+        }
+    }
+
+    impl Template {
+        /// Styles are ignored if `color` is `false`.
+        pub fn render(&self, fields: &MessageFields, color: bool) -> String {
+            let mut output = String::new();
+            for segment in &self.segments {
+                match segment {
+                    Segment::Text(text) => output.push_str(text),
+                    Segment::Field(field, styles) => {
+                        let value = fields.get(*field);
+                        if !color || styles.is_empty() || value.is_empty() {
+                            output.push_str(value);
+                            continue;
+                        }
+                        let codes = styles
+                            .iter()
+                            .map(|style| match style {
+                                Style::Code(code) => code.to_string(),
+                                Style::Auto => fields.auto_color().to_string(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(";");
+                        output.push_str(&format!("\x1b[{}m{}\x1b[0m", codes, value));
+                    }
+                }
+            }
+            output
+        }
+    }
+
+    /// Colour is disabled if `NO_COLOR` is set or stdout is not a terminal.
+    pub fn color_enabled() -> bool {
+        std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            && std::io::stdout().is_terminal()
+    }
+}
+
+pub use inner::{color_enabled, MessageFields, Template};
+
+#[cfg(test)]
+mod test {
+    use super::{MessageFields, Template};
+
+    fn fields() -> MessageFields<'static> {
+        MessageFields {
+            time: "12:00",
+            server: "Test",
+            channel: "Lobby",
+            sender: "Alice",
+            uid: "abc=",
+            mode: "CH",
+            msg: "hello",
+            own: true,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            Template::default().render(&fields(), false),
+            "[12:00] [CH] [Test] Alice (you): hello"
+        );
+        let template: Template = "{{{channel}}} {sender:red+bold}: {msg:}".parse().unwrap();
+        assert_eq!(template.render(&fields(), false), "{Lobby} Alice: hello");
+        assert_eq!(
+            template.render(&fields(), true),
+            "{Lobby} \x1b[31;1mAlice\x1b[0m: hello"
+        );
+    }
+
+    #[test]
+    fn test_auto_color_is_stable() {
+        let template: Template = "{sender:auto}".parse().unwrap();
+        let first = template.render(&fields(), true);
+        assert_eq!(first, template.render(&fields(), true));
+        let other = MessageFields {
+            sender: "Renamed",
+            ..fields()
+        };
+        // Colour follows uid instead of nickname
+        assert_eq!(
+            first.split('m').next(),
+            template.render(&other, true).split('m').next()
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!("{unknown}".parse::<Template>().is_err());
+        assert!("{sender:sparkle}".parse::<Template>().is_err());
+        assert!("{sender".parse::<Template>().is_err());
+        assert!("}".parse::<Template>().is_err());
+    }
+}
@@ -1,14 +1,80 @@
 mod inner {
+    use crate::command::CommandRegistry;
+    use crate::completion::{CompletionCache, InputHelper};
     use crate::datastructures::TransmissionCommand;
     use anyhow::anyhow;
-    use log::{error, trace};
+    use log::{error, trace, warn};
     use rustyline::error::ReadlineError;
-    use rustyline::DefaultEditor;
+    use rustyline::history::FileHistory;
+    use rustyline::{
+        Cmd, CompletionType, ConditionalEventHandler, Config, Editor, Event, EventContext,
+        EventHandler, KeyCode, KeyEvent, Modifiers, RepeatCount,
+    };
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, RwLock};
     use std::thread::JoinHandle;
     use tap::TapFallible;
-    use tempfile::NamedTempFile;
     use tokio::sync::mpsc;
 
+    type InputEditor = Editor<InputHelper, FileHistory>;
+
+    #[derive(Clone, Debug)]
+    pub struct HistoryOptions {
+        /// `None` to keep history in memory only
+        pub path: Option<PathBuf>,
+        pub max_size: usize,
+        /// Do not record lines starting with a space
+        pub ignore_space: bool,
+    }
+
+    impl HistoryOptions {
+        /// `$XDG_DATA_HOME/teamspeak-input-helper/history.txt`
+        pub fn default_path() -> Option<PathBuf> {
+            dirs::data_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("history.txt"))
+        }
+    }
+
+    /// Prompt text shared with async side, applied on next line.
+    #[derive(Clone, Debug, Default)]
+    pub struct PromptStatus {
+        inner: Arc<RwLock<String>>,
+    }
+
+    impl PromptStatus {
+        pub fn set(&self, prompt: String) {
+            *self.inner.write().unwrap() = prompt;
+        }
+
+        pub fn get(&self) -> String {
+            self.inner.read().unwrap().clone()
+        }
+    }
+
+    /// Swap Enter and Alt-Enter when multi-line mode is enabled.
+    ///
+    /// Alt-Enter always inserts a newline when multi-line mode is disabled.
+    struct MultiLineHandler {
+        enabled: Arc<AtomicBool>,
+        alt: bool,
+    }
+
+    impl ConditionalEventHandler for MultiLineHandler {
+        fn handle(
+            &self,
+            _evt: &Event,
+            _n: RepeatCount,
+            _positive: bool,
+            _ctx: &EventContext,
+        ) -> Option<Cmd> {
+            match (self.enabled.load(Ordering::Relaxed), self.alt) {
+                (true, false) | (false, true) => Some(Cmd::Newline),
+                (true, true) => Some(Cmd::AcceptLine),
+                (false, false) => None,
+            }
+        }
+    }
+
     #[derive(Debug)]
     pub struct InputThread {
         handle: JoinHandle<anyhow::Result<()>>,
@@ -26,34 +92,122 @@ mod inner {
             ret
         }
 
-        // Known issue, may override C-c function after program exit
-        pub fn get_input(sender: mpsc::Sender<TransmissionCommand>) -> anyhow::Result<()> {
-            let tmp_file = NamedTempFile::new()
-                .tap_err(|e| error!("[Can be safety ignore] Unable create temp file: {:?}", e))
-                .ok();
-            let mut rl = DefaultEditor::new()?;
-
-            let mut success = false;
-            if let Some(file) = tmp_file {
-                rl.load_history(file.path())
-                    .map(|_| success = true)
-                    .tap_err(|e| error!("[Can be safety ignore] Unable load file history. {:?}", e))
-                    .ok();
+        /// Keep indentation of multi-line message, only surrounding blank lines are removed.
+        fn trim_input(line: &str) -> &str {
+            if line.contains('\n') {
+                line.trim_matches('\n').trim_end()
+            } else {
+                line.trim()
+            }
+        }
+
+        /// PageUp and PageDown search history by typed prefix, Ctrl-R and Ctrl-S are rustyline builtin.
+        fn bind_history_search(rl: &mut InputEditor) {
+            rl.bind_sequence(
+                KeyEvent(KeyCode::PageUp, Modifiers::NONE),
+                Cmd::HistorySearchBackward,
+            );
+            rl.bind_sequence(
+                KeyEvent(KeyCode::PageDown, Modifiers::NONE),
+                Cmd::HistorySearchForward,
+            );
+        }
+
+        /// Load history file, return `false` if it should not be written.
+        fn load_history(rl: &mut InputEditor, path: &Path) -> bool {
+            if let Some(parent) = path.parent() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    error!(
+                        "Unable create history directory {}: {:?}",
+                        parent.display(),
+                        e
+                    );
+                    return false;
+                }
+            }
+            if !path.exists() {
+                return true;
             }
+            rl.load_history(path)
+                .tap_err(|e| error!("Unable load history from {}: {:?}", path.display(), e))
+                .is_ok()
+        }
+
+        fn bind_multi_line(rl: &mut InputEditor, registry: &mut CommandRegistry) {
+            let enabled = Arc::new(AtomicBool::new(false));
+            for alt in [false, true] {
+                rl.bind_sequence(
+                    KeyEvent(
+                        KeyCode::Enter,
+                        if alt { Modifiers::ALT } else { Modifiers::NONE },
+                    ),
+                    EventHandler::Conditional(Box::new(MultiLineHandler {
+                        enabled: enabled.clone(),
+                        alt,
+                    })),
+                );
+            }
+            registry.register(
+                "ml",
+                "/ml",
+                "Toggle multi-line mode, Enter inserts newline and Alt-Enter sends message",
+                move |_| {
+                    let enabled = !enabled.fetch_xor(true, Ordering::Relaxed);
+                    if enabled {
+                        println!("[*] Multi-line mode enabled, press Alt-Enter to send");
+                    } else {
+                        println!("[*] Multi-line mode disabled, press Alt-Enter to insert newline");
+                    }
+                    Ok(None)
+                },
+            );
+        }
+
+        // Known issue, may override C-c function after program exit
+        pub fn get_input(
+            sender: mpsc::Sender<TransmissionCommand>,
+            mut registry: CommandRegistry,
+            prompt: PromptStatus,
+            history: HistoryOptions,
+            completion: CompletionCache,
+        ) -> anyhow::Result<()> {
+            let config = Config::builder()
+                .max_history_size(history.max_size)?
+                .history_ignore_dups(true)?
+                .completion_type(CompletionType::List)
+                .build();
+            let mut rl = InputEditor::with_config(config)?;
+            Self::bind_multi_line(&mut rl, &mut registry);
+            Self::bind_history_search(&mut rl);
+            rl.set_helper(Some(InputHelper::new(completion, registry.names())));
+
+            let history_file = history
+                .path
+                .filter(|path| Self::load_history(&mut rl, path));
 
             loop {
-                match rl.readline(">> ") {
+                match rl.readline(&prompt.get()) {
                     Ok(line) => {
-                        if line.is_empty() {
+                        let input = Self::trim_input(&line);
+                        if input.is_empty() {
                             continue;
                         }
-                        if success {
-                            rl.add_history_entry(line.trim()).ok();
+                        if !(history.ignore_space && line.starts_with(' '))
+                            && rl.add_history_entry(input).unwrap_or_default()
+                        {
+                            if let Some(path) = &history_file {
+                                rl.append_history(path)
+                                    .tap_err(|e| warn!("Unable save history: {:?}", e))
+                                    .ok();
+                            }
+                        }
+                        match registry.dispatch(input) {
+                            Ok(Some(command)) => {
+                                Self::send_data(sender.clone(), command);
+                            }
+                            Ok(None) => {}
+                            Err(e) => println!("[!] {}", e),
                         }
-                        Self::send_data(
-                            sender.clone(),
-                            TransmissionCommand::Data(line.trim().to_string()),
-                        );
                         trace!("Read {} bytes from stdin", line.len());
                     }
                     Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
@@ -68,9 +222,17 @@ mod inner {
             Ok(())
         }
 
-        pub fn start(sender: mpsc::Sender<TransmissionCommand>) -> Self {
+        pub fn start(
+            sender: mpsc::Sender<TransmissionCommand>,
+            registry: CommandRegistry,
+            prompt: PromptStatus,
+            history: HistoryOptions,
+            completion: CompletionCache,
+        ) -> Self {
             Self {
-                handle: std::thread::spawn(|| Self::get_input(sender)),
+                handle: std::thread::spawn(|| {
+                    Self::get_input(sender, registry, prompt, history, completion)
+                }),
             }
         }
 
@@ -87,4 +249,4 @@ mod inner {
     }
 }
 
-pub use inner::InputThread;
+pub use inner::{HistoryOptions, InputThread, PromptStatus};
@@ -0,0 +1,213 @@
+mod inner {
+    use crate::chat_log::ChatRecord;
+    use crate::datastructures::TargetMode;
+    use crate::format::{MessageFields, Template};
+    use anyhow::anyhow;
+    use chrono::NaiveDate;
+    use log::warn;
+    use regex::Regex;
+    use std::path::{Path, PathBuf};
+
+    /// Conditions of `log search`, record should match all specified conditions.
+    #[derive(Debug, Default)]
+    pub struct SearchFilter {
+        /// Match sender nickname (case-insensitive) or uid
+        pub sender: Option<String>,
+        /// Match server name (case-insensitive) or unique identifier
+        pub server: Option<String>,
+        pub since: Option<NaiveDate>,
+        pub until: Option<NaiveDate>,
+        pub mode: Option<TargetMode>,
+        pub pattern: Option<Regex>,
+    }
+
+    impl SearchFilter {
+        fn in_range(&self, date: NaiveDate) -> bool {
+            self.since.is_none_or(|since| date >= since)
+                && self.until.is_none_or(|until| date <= until)
+        }
+
+        pub fn matches(&self, record: &ChatRecord) -> bool {
+            self.sender.as_ref().is_none_or(|sender| {
+                record.sender.eq_ignore_ascii_case(sender) || record.sender_uid.eq(sender)
+            }) && self.server.as_ref().is_none_or(|server| {
+                record.server.eq_ignore_ascii_case(server) || record.server_uid.eq(server)
+            }) && self.in_range(record.timestamp.date_naive())
+                && self.mode.is_none_or(|mode| record.mode == Some(mode))
+                && self
+                    .pattern
+                    .as_ref()
+                    .is_none_or(|pattern| pattern.is_match(&record.message))
+        }
+    }
+
+    /// Log files in `dir` which may contain matched records.
+    fn log_files(dir: &Path, filter: &SearchFilter) -> anyhow::Result<Vec<PathBuf>> {
+        // Server may be renamed, so every directory is searched and filtered by record
+        let server_dirs = std::fs::read_dir(dir)
+            .map_err(|e| anyhow!("Unable read log directory {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>();
+        let mut files = Vec::new();
+        for server_dir in &server_dirs {
+            for entry in std::fs::read_dir(server_dir)? {
+                let path = entry?.path();
+                let date = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(".jsonl"))
+                    .and_then(|name| NaiveDate::parse_from_str(name, "%Y-%m-%d").ok());
+                // Timestamp is local time of writer, so keep one more day on each side
+                if date.is_some_and(|date| {
+                    filter
+                        .since
+                        .is_none_or(|since| date.succ_opt().unwrap_or(date) >= since)
+                        && filter
+                            .until
+                            .is_none_or(|until| date.pred_opt().unwrap_or(date) <= until)
+                }) {
+                    files.push(path);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Return matched records sorted by timestamp.
+    pub fn search(dir: &Path, filter: &SearchFilter) -> anyhow::Result<Vec<ChatRecord>> {
+        let mut records = Vec::new();
+        for path in log_files(dir, filter)? {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Unable read log file {}: {}", path.display(), e))?;
+            for (n, line) in content.lines().enumerate() {
+                match serde_json::from_str::<ChatRecord>(line) {
+                    Ok(record) if filter.matches(&record) => records.push(record),
+                    Ok(_) => {}
+                    Err(e) => warn!("Skip broken line {}:{}: {}", path.display(), n + 1, e),
+                }
+            }
+        }
+        records.sort_by_key(|record| record.timestamp);
+        Ok(records)
+    }
+
+    /// Format record like messages displayed in chat, with same time format and template.
+    pub fn format_record(
+        record: &ChatRecord,
+        time_format: &str,
+        template: &Template,
+        color: bool,
+    ) -> String {
+        let time = record
+            .timestamp
+            .with_timezone(&chrono::Local)
+            .format(time_format)
+            .to_string();
+        let fields = MessageFields {
+            time: &time,
+            server: &record.server,
+            sender: &record.sender,
+            uid: &record.sender_uid,
+            mode: record.mode.map_or("?", |mode| mode.label()),
+            msg: &record.message,
+            own: record.outgoing,
+            ..Default::default()
+        };
+        template.render(&fields, color)
+    }
+}
+
+pub use inner::{format_record, search, SearchFilter};
+
+#[cfg(test)]
+mod test {
+    use super::{format_record, search, SearchFilter};
+    use crate::chat_log::{ChatLogger, ChatRecord};
+    use crate::datastructures::TargetMode;
+    use crate::format::Template;
+    use chrono::{DateTime, NaiveDate};
+    use regex::Regex;
+
+    fn record(timestamp: &str, server: i64, sender: &str, message: &str) -> ChatRecord {
+        ChatRecord {
+            timestamp: DateTime::parse_from_rfc3339(timestamp).unwrap(),
+            schandlerid: 1,
+            server: format!("Server {}", server),
+            server_uid: format!("uid{}=", server),
+            mode: Some(TargetMode::Channel),
+            sender: sender.to_string(),
+            sender_uid: format!("{}-uid", sender),
+            outgoing: false,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let mut logger = ChatLogger::new(dir.to_path_buf());
+        for r in [
+            record("2024-01-02T10:00:00+00:00", 2, "Bob", "see you tomorrow"),
+            record("2024-01-01T10:00:00+00:00", 1, "Alice", "hello world"),
+            record("2024-01-01T11:00:00+00:00", 1, "Bob", "hello alice"),
+            record("2024-01-03T10:00:00+00:00", 1, "Alice", "bye"),
+        ] {
+            logger.write(&r).unwrap();
+        }
+
+        let all = search(dir, &SearchFilter::default()).unwrap();
+        assert_eq!(
+            all.iter().map(|r| r.message.as_str()).collect::<Vec<_>>(),
+            ["hello world", "hello alice", "see you tomorrow", "bye"]
+        );
+
+        let filter = SearchFilter {
+            sender: Some("bob".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(dir, &filter).unwrap().len(), 2);
+
+        let filter = SearchFilter {
+            server: Some("server 1".to_string()),
+            pattern: Some(Regex::new("^hello").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(search(dir, &filter).unwrap().len(), 2);
+
+        let filter = SearchFilter {
+            server: Some("uid2=".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(dir, &filter).unwrap()[0].message, "see you tomorrow");
+
+        let filter = SearchFilter {
+            since: NaiveDate::from_ymd_opt(2024, 1, 2),
+            until: NaiveDate::from_ymd_opt(2024, 1, 2),
+            ..Default::default()
+        };
+        let found = search(dir, &filter).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].sender, "Bob");
+
+        let filter = SearchFilter {
+            mode: Some(TargetMode::Private),
+            ..Default::default()
+        };
+        assert!(search(dir, &filter).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_format_record() {
+        let mut r = record("2024-06-15T12:00:00+00:00", 1, "Alice", "hello");
+        r.outgoing = true;
+        let template = "{time} [{mode}] [{server}] {sender}{own}: {msg}"
+            .parse::<Template>()
+            .unwrap();
+        assert_eq!(
+            format_record(&r, "%Y", &template, false),
+            "2024 [CH] [Server 1] Alice (you): hello"
+        );
+    }
+}
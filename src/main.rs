@@ -1,120 +1,839 @@
-#![feature(result_flattening)]
-
-use crate::datastructures::{FromQueryString, NotifyTextMessage, TransmissionCommand};
-use crate::input_thread::InputThread;
+use crate::chat_log::{ChatLogger, ChatRecord};
+use crate::command::CommandRegistry;
+use crate::completion::CompletionCache;
+use crate::config::ConfigFile;
+use crate::datastructures::{
+    ClientAction, ClientSelector, EventKind, FromQueryString, MessageTarget, NotifyChannelChanged,
+    NotifyChannelDeleted, NotifyClientEnterView, NotifyClientLeftView, NotifyClientMoved,
+    NotifyClientPoke, NotifyClientUpdated, NotifyConnectStatusChange,
+    NotifyCurrentServerConnectionChanged, NotifyTalkStatusChange, NotifyTextMessage, ServerModel,
+    TargetMode, TransmissionCommand,
+};
+use crate::format::{MessageFields, Template};
+use crate::input_thread::{HistoryOptions, InputThread, PromptStatus};
+use crate::log_search::SearchFilter;
+use crate::outbound::{DeliveryState, OutboundMessage, OutboundQueue};
+use crate::rate_limit::RateLimiter;
+use crate::server_tabs::ServerTabs;
+use crate::split::{split_message, MAX_MESSAGE_LENGTH};
+use crate::supervisor::Supervisor;
 use crate::tslib::TeamspeakConnection;
 use anyhow::anyhow;
-use clap::{arg, command};
+use clap::{arg, command, ArgMatches, Command};
 use kstool::prelude::get_current_duration;
-use log::{error, info, warn, LevelFilter};
+use log::{error, info, trace, warn, LevelFilter};
 use std::hint::unreachable_unchecked;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tap::TapFallible;
-use tokio::sync::mpsc;
-use tokio::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{Duration, Instant};
 
+mod api_key;
+mod channel_tree;
+mod chat_log;
+mod command;
+mod completion;
+mod config;
 mod datastructures;
+mod escape;
+mod format;
 mod input_thread;
+mod log_search;
+#[cfg(test)]
+mod mock_server;
+mod outbound;
+mod private_file;
+mod rate_limit;
+mod server_tabs;
+mod split;
+mod supervisor;
 mod tslib;
 
 const DEFAULT_VARIABLE_NAME: &str = "TS_CLIENT_QUERY_APIKEY";
-const TRANSMISSION_DEADLINE: u64 = 180;
+const DEFAULT_KEEPALIVE_INTERVAL: u64 = 180;
+const DEFAULT_COMMAND_QUEUE_SIZE: usize = 4096;
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// How long to wait for connection to send remaining messages after user request exit
+const EXIT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
-async fn real_staff(
-    mut conn: TeamspeakConnection,
-    last_transmission: Arc<AtomicU64>,
-    mut command_receiver: mpsc::Receiver<TransmissionCommand>,
+#[derive(Debug)]
+struct DisplayOptions {
+    hide_own_messages: bool,
+    /// strftime format of timestamp
+    time_format: String,
+    template: Template,
+    color: bool,
+    /// Client events printed to console
+    events: Vec<EventKind>,
+}
+
+/// State kept across reconnecting.
+#[derive(Debug)]
+struct SessionState {
+    outbound: OutboundQueue,
+    limiter: RateLimiter,
+    target: MessageTarget,
+    tabs: ServerTabs,
+    prompt: PromptStatus,
+    model: ServerModel,
+    completion: CompletionCache,
+    /// Active tab and model revision which completion is built from
+    completion_source: Option<(i64, u64)>,
+    display: DisplayOptions,
+    split_markers: bool,
+    chat_log: Option<ChatLogger>,
+    /// User requested exit, remaining messages are still sent before exit
+    exiting: bool,
+}
+
+impl SessionState {
+    fn new(
+        pinned: Option<i64>,
+        prompt: PromptStatus,
+        target: MessageTarget,
+        display: DisplayOptions,
+        split_markers: bool,
+        limiter: RateLimiter,
+        chat_log: Option<ChatLogger>,
+    ) -> Self {
+        let ret = Self {
+            outbound: Default::default(),
+            limiter,
+            target,
+            tabs: ServerTabs::new(pinned),
+            prompt,
+            model: Default::default(),
+            completion: Default::default(),
+            completion_source: None,
+            display,
+            split_markers,
+            chat_log,
+            exiting: false,
+        };
+        ret.update_prompt();
+        ret
+    }
+
+    fn nickname(&self, server_id: i64, client_id: i64) -> String {
+        self.model
+            .server(server_id)
+            .and_then(|tables| tables.client(client_id))
+            .map(|client| client.nickname().to_string())
+            .unwrap_or_else(|| format!("clid={}", client_id))
+    }
+
+    fn channel_name(&self, server_id: i64, channel_id: i64) -> String {
+        self.model
+            .server(server_id)
+            .and_then(|tables| tables.channel(channel_id))
+            .map(|channel| channel.name().to_string())
+            .unwrap_or_else(|| format!("#{}", channel_id))
+    }
+
+    fn print_client_event(&self, kind: EventKind, text: String, reason: &str) {
+        if self.display.events.contains(&kind) {
+            self.print_event(&with_reason(text, reason));
+        }
+    }
+
+    fn timestamp(&self) -> String {
+        chrono::Local::now()
+            .format(&self.display.time_format)
+            .to_string()
+    }
+
+    fn log_chat(&mut self, record: ChatRecord) {
+        if let Some(logger) = &mut self.chat_log {
+            logger
+                .write(&record)
+                .tap_err(|e| error!("Unable write chat log: {:?}", e))
+                .ok();
+        }
+    }
+
+    /// Log sent message which echo is not received.
+    fn log_unconfirmed(&mut self, message: &OutboundMessage) {
+        let server_id = message.server_id().unwrap_or_else(|| self.tabs.active());
+        let (sender, sender_uid) = self
+            .tabs
+            .client_id(server_id)
+            .and_then(|client_id| self.model.server(server_id)?.client(client_id))
+            .map(|me| (me.nickname().to_string(), me.uid().to_string()))
+            .unwrap_or_default();
+        self.log_chat(ChatRecord {
+            timestamp: chrono::Local::now().fixed_offset(),
+            schandlerid: server_id,
+            server: self.tabs.label(server_id),
+            server_uid: self
+                .tabs
+                .unique_id(server_id)
+                .unwrap_or_default()
+                .to_string(),
+            mode: Some(message.target().mode()),
+            sender,
+            sender_uid,
+            outgoing: true,
+            message: message.text().to_string(),
+        });
+    }
+
+    fn print_event(&self, text: &str) {
+        println!("[{}] * {}", self.timestamp(), text);
+    }
+
+    /// Offer names of active tab to completion, only rebuilt if active tab or model is changed.
+    fn update_completion(&mut self) {
+        let source = (self.tabs.active(), self.model.revision());
+        if self.completion_source == Some(source) {
+            return;
+        }
+        self.completion_source = Some(source);
+        match self.model.server(self.tabs.active()) {
+            Some(tables) => self.completion.set(
+                tables
+                    .clients()
+                    .map(|client| client.nickname().to_string())
+                    .collect(),
+                tables
+                    .channels()
+                    .map(|channel| channel.name().to_string())
+                    .collect(),
+            ),
+            None => self.completion.set(Vec::new(), Vec::new()),
+        }
+    }
+
+    fn update_prompt(&self) {
+        let mut prompt = match self.tabs.active_name() {
+            Some(name) => format!("[{}] ", name),
+            None => String::new(),
+        };
+        match self.outbound.pending() {
+            0 => {}
+            n => prompt.push_str(&format!("({} pending) ", n)),
+        }
+        prompt.push_str(">> ");
+        self.prompt.set(prompt);
+    }
+
+    /// Next time point which outbound queue should be processed again.
+    fn next_wakeup(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let throttled = self
+            .outbound
+            .next_ready(now)
+            .map(|_| self.limiter.next_available(now));
+        self.outbound
+            .next_wakeup()
+            .into_iter()
+            .chain(throttled)
+            .min()
+    }
+
+    /// Bind message to the tab shown in prompt, so switching tab later will not affect it.
+    fn capture_server(&self) -> Option<i64> {
+        self.tabs
+            .active_name()
+            .is_some()
+            .then(|| self.tabs.active())
+    }
+
+    /// Split message if it is too long.
+    fn push_message(&mut self, target: MessageTarget, text: &str) {
+        let parts = split_message(text, MAX_MESSAGE_LENGTH, self.split_markers);
+        if parts.len() > 1 {
+            info!("Message is too long, split into {} parts", parts.len());
+        }
+        self.outbound
+            .push_parts(self.capture_server(), target, parts);
+    }
+
+    /// User requested exit and every message is delivered or failed.
+    fn should_exit(&self) -> bool {
+        self.exiting && self.outbound.is_settled()
+    }
+
+    fn handle_command(&mut self, command: TransmissionCommand) {
+        match command {
+            TransmissionCommand::Data(s) => {
+                self.push_message(self.target.clone(), &s);
+            }
+            TransmissionCommand::Message(target, s) => {
+                self.push_message(target, &s);
+            }
+            TransmissionCommand::SetTarget(target) => {
+                println!("[*] Messages will be sent as {}", target);
+                self.target = target;
+            }
+            TransmissionCommand::SelectTab(Some(server_id)) if !self.tabs.contains(server_id) => {
+                println!(
+                    "[!] Server tab {} not found, type /tab to list tabs",
+                    server_id
+                );
+            }
+            TransmissionCommand::SelectTab(pinned) => {
+                self.tabs.set_pinned(pinned);
+                match pinned {
+                    Some(server_id) => println!("[*] Pinned to server tab {}", server_id),
+                    None => println!("[*] Follow current server tab"),
+                }
+                self.update_prompt();
+            }
+            TransmissionCommand::ListTabs => {
+                println!("{}", self.tabs.describe());
+            }
+            TransmissionCommand::ListClients
+            | TransmissionCommand::ListChannels
+            | TransmissionCommand::Action(_) => {
+                println!("[!] Not connected to ClientQuery, try again later");
+            }
+            TransmissionCommand::Terminate => {
+                if !self.outbound.is_settled() {
+                    println!(
+                        "[*] Sending remaining messages before exit, press Ctrl-C to force exit"
+                    );
+                }
+                self.exiting = true;
+            }
+        }
+    }
+}
+
+/// Load clients and channels of connected tabs, notifications keep them updated afterwards.
+async fn refresh_model(conn: &TeamspeakConnection, state: &mut SessionState) {
+    state.model.clear();
+    for server_id in state.tabs.connected() {
+        refresh_tab_model(conn, state, server_id).await;
+    }
+}
+
+/// Load clients and channels of tab.
+async fn refresh_tab_model(conn: &TeamspeakConnection, state: &mut SessionState, server_id: i64) {
+    match conn.get_clients(server_id).await {
+        Ok(clients) => state.model.set_clients(server_id, &clients),
+        Err(e) => warn!("Unable get client list of tab {}: {:?}", server_id, e),
+    }
+    match conn.get_channels(server_id).await {
+        Ok(channels) => state.model.set_channels(server_id, &channels),
+        Err(e) => warn!("Unable get channel list of tab {}: {:?}", server_id, e),
+    }
+}
+
+fn with_reason(text: String, reason: &str) -> String {
+    if reason.is_empty() {
+        text
+    } else {
+        format!("{} ({})", text, reason)
+    }
+}
+
+/// Fetch server name, unique identifier and our client id of tab.
+async fn update_tab(conn: &TeamspeakConnection, tabs: &mut ServerTabs, server_id: i64) {
+    // Error if tab is not connected to server
+    let server = conn.get_server_name(server_id).await.ok();
+    tabs.update(server_id, server.as_ref().map(|r| r.name().to_string()));
+    if let Some(server) = server {
+        tabs.set_unique_id(server_id, server.unique_id().to_string());
+        match conn.who_am_i(server_id).await {
+            Ok(me) => tabs.set_client_id(server_id, me.client_id()),
+            Err(e) => warn!("Unable get client id of tab {}: {:?}", server_id, e),
+        }
+    }
+}
+
+/// Fetch every server tab and its name, should be called after connection established.
+async fn refresh_tabs(conn: &TeamspeakConnection, tabs: &mut ServerTabs) -> anyhow::Result<()> {
+    let server_ids = conn
+        .get_server_tabs()
+        .await?
+        .iter()
+        .map(|tab| tab.schandler_id())
+        .collect::<Vec<_>>();
+    tabs.reset(&server_ids);
+    for server_id in server_ids {
+        update_tab(conn, tabs, server_id).await;
+    }
+    tabs.set_current(conn.get_current_server_tab().await?.schandler_id());
+    if let Some(pinned) = tabs.pinned().filter(|id| !tabs.contains(*id)) {
+        warn!("Pinned server tab {} is not exist", pinned);
+    }
+    Ok(())
+}
+
+/// Handle `/who` and `/channels` with fresh client and channel list of active tab.
+async fn list_clients(conn: &TeamspeakConnection, state: &mut SessionState, tree: bool) {
+    let server_id = state.tabs.active();
+    refresh_tab_model(conn, state, server_id).await;
+    let Some(tables) = state.model.server(server_id) else {
+        println!("[!] Server tab {} is not connected", server_id);
+        return;
+    };
+    let own_client = state.tabs.client_id(server_id);
+    if tree {
+        println!("{}", channel_tree::render_tree(tables, own_client));
+        return;
+    }
+    match own_client.and_then(|client_id| tables.channel_of(client_id)) {
+        Some(channel) => {
+            println!("[*] Clients in {}:", channel.name());
+            for line in channel_tree::render_clients(tables, channel.channel_id(), own_client, 1) {
+                println!("{}", line);
+            }
+        }
+        None => println!("[!] Unable find your channel in server tab {}", server_id),
+    }
+}
+
+/// Handle `/join`, `/nick`, `/away`, `/mute`, `/deafen` and `/poke` on active tab.
+async fn run_action(conn: &TeamspeakConnection, state: &SessionState, action: ClientAction) {
+    let server_id = state.tabs.active();
+    let (Some(tables), Some(own_client)) = (
+        state.model.server(server_id),
+        state.tabs.client_id(server_id),
+    ) else {
+        println!("[!] Server tab {} is not connected", server_id);
+        return;
+    };
+    let me = tables.client(own_client);
+    let command = action.command_name();
+    let (done, ret) = match action {
+        ClientAction::Join(channel) => {
+            // Channel id is sent even if it is not in model, server will report if it is invalid
+            let Some((channel_id, name)) = tables
+                .find_channel(&channel)
+                .map(|c| (c.channel_id(), c.name().to_string()))
+                .or_else(|| {
+                    let channel_id = channel.parse().ok()?;
+                    let name = tables
+                        .channel(channel_id)
+                        .map_or_else(|| format!("#{}", channel_id), |c| c.name().to_string());
+                    Some((channel_id, name))
+                })
+            else {
+                println!("[!] Channel not found: {}", channel);
+                return;
+            };
+            (
+                format!("Joined channel {}", name),
+                conn.move_client(server_id, own_client, channel_id).await,
+            )
+        }
+        ClientAction::Nick(nickname) => (
+            format!("Nickname changed to {}", nickname),
+            conn.set_nickname(server_id, &nickname).await,
+        ),
+        ClientAction::Away(message) => {
+            let away = message.is_some() || !me.is_some_and(|me| me.is_away());
+            let message = message.as_deref().unwrap_or_default();
+            (
+                match (away, message) {
+                    (false, _) => "No longer away".to_string(),
+                    (true, "") => "Marked as away".to_string(),
+                    (true, message) => format!("Marked as away: {}", message),
+                },
+                conn.set_away(server_id, away.then_some(message)).await,
+            )
+        }
+        ClientAction::Mute => {
+            let muted = !me.is_some_and(|me| me.is_input_muted());
+            (
+                format!("Microphone {}", if muted { "muted" } else { "unmuted" }),
+                conn.set_input_muted(server_id, muted).await,
+            )
+        }
+        ClientAction::Deafen => {
+            let muted = !me.is_some_and(|me| me.is_output_muted());
+            (
+                format!("Speakers {}", if muted { "muted" } else { "unmuted" }),
+                conn.set_output_muted(server_id, muted).await,
+            )
+        }
+        ClientAction::Poke(selector, message) => {
+            let client = match &selector {
+                ClientSelector::Id(client_id) => Ok(*client_id),
+                ClientSelector::Nickname(name) => {
+                    tables.find_client(name).map(|client| client.client_id())
+                }
+            };
+            let name = client
+                .as_ref()
+                .ok()
+                .and_then(|client_id| tables.client(*client_id))
+                .map_or_else(|| selector.to_string(), |c| c.nickname().to_string());
+            let ret = match client {
+                Ok(client_id) => conn.poke_client(server_id, client_id, &message).await,
+                Err(e) => Err(e),
+            };
+            (format!("Poked {}", name), ret)
+        }
+    };
+    match ret {
+        Ok(_) => println!("[*] {}", done),
+        Err(e) => println!("[!] {} failed: {}", command, e),
+    }
+}
+
+/// Send pending messages in order, stop at first message which can't be sent for now.
+async fn process_outbound(
+    conn: &TeamspeakConnection,
+    last_transmission: &AtomicU64,
+    state: &mut SessionState,
 ) -> anyhow::Result<()> {
-    loop {
-        tokio::select! {
-            Some(data) = command_receiver.recv() => {
-                match data {
-                    TransmissionCommand::Data(s) => {
-                        let server_id = conn
-                            .get_current_server_tab()
-                            .await
-                            .tap_err(|e| warn!("Can't get current server tab: {:?}", e))
-                            .map(|r| r.schandler_id())
-                            .ok()
-                            .unwrap_or(1);
-                        conn.send_channel_message(server_id, &s)
-                            .await
-                            .tap_err(|e| error!("Unable send channel message: {:?}", e))
-                            .ok();
-                        last_transmission.store(get_current_duration().as_secs(), Ordering::Relaxed);
-                    }
-                    TransmissionCommand::KeepAlive => {
-                        conn.keep_alive()
-                            .await
-                            .tap_err(|e| {
-                                error!("Got error while write data in keep alive function: {:?}", e)
-                            })
-                            .ok();
-                        last_transmission.store(get_current_duration().as_secs(), Ordering::Relaxed);
-                    }
-                    TransmissionCommand::Terminate => {
-                        return Ok(());
-                    }
+    let outbound = &mut state.outbound;
+    while let Some(message) = outbound.next_ready(Instant::now()) {
+        if !state.limiter.try_acquire(Instant::now()) {
+            break;
+        }
+        let (id, server_id, target, text) = (
+            message.id(),
+            message.server_id().unwrap_or_else(|| state.tabs.active()),
+            message.target().clone(),
+            message.text().to_string(),
+        );
+        let ret = conn.send_message(server_id, &target, &text).await;
+        last_transmission.store(get_current_duration().as_secs(), Ordering::Relaxed);
+        match ret {
+            Ok(_) => {
+                state.limiter.on_success();
+                outbound.mark_sent(id);
+            }
+            Err(e) if conn.is_closed() => {
+                return Err(anyhow!("Connection lost while sending message: {:?}", e));
+            }
+            Err(e) if e.is_retryable() => {
+                warn!("Unable send message, will retry later: {}", e);
+                if e.is_flooding() {
+                    state.limiter.on_flood(Instant::now());
+                }
+                if outbound.retry_later(id, e.to_string()) {
+                    break;
                 }
             }
-            ret = conn.wait_readable() => {
-                if !ret? {
-                    continue;
+            Err(e) => outbound.mark_failed(id, e.to_string()),
+        }
+    }
+
+    for message in outbound.drain_finished(Instant::now()) {
+        match message.state() {
+            DeliveryState::Failed => println!(
+                "[!] Message not delivered: {} ({})",
+                message.text(),
+                message.last_error().unwrap_or_default()
+            ),
+            _ => {
+                if let Some(e) = message.last_error() {
+                    warn!("Message {} is not confirmed: {}", message.id(), e);
+                    state.log_unconfirmed(&message);
                 }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_text_message(state: &mut SessionState, view: NotifyTextMessage) {
+    let is_own = state.tabs.client_id(view.server_id()) == Some(view.invoker_id());
+    state.log_chat(ChatRecord {
+        timestamp: chrono::Local::now().fixed_offset(),
+        schandlerid: view.server_id(),
+        server: state.tabs.label(view.server_id()),
+        server_uid: state
+            .tabs
+            .unique_id(view.server_id())
+            .unwrap_or_default()
+            .to_string(),
+        mode: view.target_mode(),
+        sender: view.invoker_name().to_string(),
+        sender_uid: view.invoker_uid().to_string(),
+        outgoing: is_own,
+        message: view.msg().to_string(),
+    });
+    if is_own {
+        if let Some(id) = state.outbound.confirm(view.msg()) {
+            trace!("Message {} confirmed", id);
+        }
+        if state.display.hide_own_messages {
+            return;
+        }
+    }
 
-                let data = conn
-                    .read_data()
-                    .await
-                    .map_err(|e| anyhow!("Got error while read data: {:?}", e))?;
-                //trace!("Read data end");
+    let (time, server) = (state.timestamp(), state.tabs.label(view.server_id()));
+    let channel = state
+        .model
+        .server(view.server_id())
+        .and_then(|tables| tables.channel_of(view.invoker_id()))
+        .map(|channel| channel.name().to_string())
+        .unwrap_or_default();
+    let fields = MessageFields {
+        time: &time,
+        server: &server,
+        channel: &channel,
+        sender: view.invoker_name(),
+        uid: view.invoker_uid(),
+        mode: view.target_mode().map_or("?", |mode| mode.label()),
+        msg: view.msg(),
+        own: is_own,
+    };
+    println!(
+        "{}",
+        state.display.template.render(&fields, state.display.color)
+    );
+}
 
-                if !data.as_ref().is_some_and(|x| !x.is_empty()) {
-                    continue;
+/// Decode every record of notification, broken records are logged and skipped.
+fn decode_records<T: FromQueryString>(line: &str) -> Vec<T> {
+    T::from_query_records(line)
+        .into_iter()
+        .filter_map(|record| {
+            record
+                .tap_err(|e| warn!("Skip undecodable notification {:?}: {:?}", line, e))
+                .ok()
+        })
+        .collect()
+}
+
+async fn handle_notification(conn: &TeamspeakConnection, state: &mut SessionState, line: &str) {
+    let (name, _) = line.split_once(' ').unwrap_or((line, ""));
+    match name {
+        "notifytextmessage" => {
+            for view in decode_records::<NotifyTextMessage>(line) {
+                handle_text_message(state, view);
+            }
+        }
+        "notifycurrentserverconnectionchanged" => {
+            for view in decode_records::<NotifyCurrentServerConnectionChanged>(line) {
+                state.tabs.set_current(view.server_id());
+            }
+            state.update_prompt();
+        }
+        "notifyconnectstatuschange" => {
+            for view in decode_records::<NotifyConnectStatusChange>(line) {
+                if view.is_established() {
+                    update_tab(conn, &mut state.tabs, view.server_id()).await;
+                    refresh_tab_model(conn, state, view.server_id()).await;
+                } else if view.is_disconnected() {
+                    if state.tabs.pinned() == Some(view.server_id()) {
+                        println!("[!] Pinned server tab {} is disconnected", view.server_id());
+                    }
+                    state.tabs.set_disconnected(view.server_id());
+                    state.model.remove_server(view.server_id());
+                } else {
+                    trace!("Server tab {} status: {}", view.server_id(), view.status());
                 }
-                let data = data.unwrap();
+                state.update_prompt();
+            }
+        }
+        "notifycliententerview" => {
+            for view in decode_records::<NotifyClientEnterView>(line) {
+                state.model.client_entered(&view);
+                state.print_client_event(
+                    EventKind::EnterView,
+                    format!(
+                        "{} joined channel {}",
+                        view.nickname(),
+                        state.channel_name(view.server_id(), view.channel_id())
+                    ),
+                    "",
+                );
+            }
+        }
+        "notifyclientleftview" => {
+            for view in decode_records::<NotifyClientLeftView>(line) {
+                let nickname = state
+                    .model
+                    .client_left(view.server_id(), view.client_id())
+                    .map(|client| client.nickname().to_string())
+                    .unwrap_or_else(|| format!("clid={}", view.client_id()));
+                let text = match view.reason_id() {
+                    3 => format!("{} lost connection", nickname),
+                    5 => format!(
+                        "{} was kicked from server by {}",
+                        nickname,
+                        view.invoker_name()
+                    ),
+                    6 => format!("{} was banned by {}", nickname, view.invoker_name()),
+                    _ => format!("{} left", nickname),
+                };
+                state.print_client_event(EventKind::LeftView, text, view.reason_msg());
+            }
+        }
+        "notifyclientmoved" => {
+            for view in decode_records::<NotifyClientMoved>(line) {
+                state
+                    .model
+                    .client_moved(view.server_id(), view.client_id(), view.channel_id());
+                let nickname = state.nickname(view.server_id(), view.client_id());
+                let channel = state.channel_name(view.server_id(), view.channel_id());
+                let text = match view.reason_id() {
+                    1 => format!(
+                        "{} was moved to channel {} by {}",
+                        nickname,
+                        channel,
+                        view.invoker_name()
+                    ),
+                    4 => format!(
+                        "{} was kicked from channel by {}",
+                        nickname,
+                        view.invoker_name()
+                    ),
+                    _ => format!("{} switched to channel {}", nickname, channel),
+                };
+                state.print_client_event(EventKind::Moved, text, view.reason_msg());
+            }
+        }
+        "notifyclientpoke" => {
+            for view in decode_records::<NotifyClientPoke>(line) {
+                state.print_client_event(
+                    EventKind::Poke,
+                    format!("{} poked you", view.invoker_name()),
+                    view.msg(),
+                );
+            }
+        }
+        "notifyclientupdated" => {
+            for view in decode_records::<NotifyClientUpdated>(line) {
+                state.model.client_updated(&view);
+            }
+        }
+        "notifytalkstatuschange" => {
+            for view in decode_records::<NotifyTalkStatusChange>(line) {
+                state.model.talk_status_changed(
+                    view.server_id(),
+                    view.client_id(),
+                    view.is_talking(),
+                );
+            }
+        }
+        "notifychannelcreated" | "notifychanneledited" | "notifychannelmoved" => {
+            for view in decode_records::<NotifyChannelChanged>(line) {
+                state.model.channel_changed(&view);
+            }
+        }
+        "notifychanneldeleted" => {
+            for view in decode_records::<NotifyChannelDeleted>(line) {
+                state
+                    .model
+                    .channel_deleted(view.server_id(), view.channel_id());
+            }
+        }
+        _ => {}
+    }
+}
 
-                //trace!("message loop start");
-                for line in data.lines().map(|line| line.trim()) {
-                    if line.is_empty() {
+/// Return `Ok(())` if user request exit, or error if connection is lost.
+async fn real_staff(
+    conn: TeamspeakConnection,
+    last_transmission: Arc<AtomicU64>,
+    command_receiver: &mut mpsc::Receiver<TransmissionCommand>,
+    state: &mut SessionState,
+) -> anyhow::Result<()> {
+    let mut notify_receiver = conn.subscribe();
+    refresh_tabs(&conn, &mut state.tabs)
+        .await
+        .map_err(|e| anyhow!("Unable fetch server tabs: {:?}", e))?;
+    state.update_prompt();
+    refresh_model(&conn, state).await;
+    loop {
+        process_outbound(&conn, &last_transmission, state).await?;
+        if state.should_exit() {
+            return Ok(());
+        }
+        state.update_prompt();
+        state.update_completion();
+        let wakeup = state.next_wakeup();
+        tokio::select! {
+            Some(data) = command_receiver.recv() => match data {
+                TransmissionCommand::ListClients => list_clients(&conn, state, false).await,
+                TransmissionCommand::ListChannels => list_clients(&conn, state, true).await,
+                TransmissionCommand::Action(action) => run_action(&conn, state, action).await,
+                data => state.handle_command(data),
+            },
+            _ = tokio::time::sleep_until(wakeup.unwrap_or_else(Instant::now)), if wakeup.is_some() => {}
+            _ = conn.closed() => {
+                return Err(anyhow!("Connection closed"));
+            }
+            line = notify_receiver.recv() => {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Notification receiver lagged, {} notifications skipped, reload client and channel lists", n);
+                        refresh_model(&conn, state).await;
                         continue;
                     }
+                    Err(broadcast::error::RecvError::Closed) => unreachable!(),
+                };
+
+                handle_notification(&conn, state, &line).await;
+            }
+        }
+    }
+}
 
-                    if line.contains("notifytextmessage") {
-                        let view = NotifyTextMessage::from_query(line)
-                            .map_err(|e| anyhow!("Got error while deserialize moved view: {:?}", e))?;
+async fn keep_alive_staff(
+    conn: TeamspeakConnection,
+    last_transmission: Arc<AtomicU64>,
+    interval: u64,
+) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        if get_current_duration().as_secs() - last_transmission.load(Ordering::Relaxed) > interval {
+            conn.keep_alive()
+                .await
+                .tap_err(|e| error!("Got error while write data in keep alive function: {:?}", e))
+                .ok();
+            last_transmission.store(get_current_duration().as_secs(), Ordering::Relaxed);
+        }
+    }
+}
 
-                        println!(
-                            "[{time}] {sender}: {msg}",
-                            time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                            sender = view.invoker_name(),
-                            msg = view.msg()
-                        );
-                        continue;
-                    }
+/// Wait until connection established, messages typed in the meantime will be held.
+///
+/// Return `None` if user request exit, and either nothing is left to send or connection
+/// is not established in time.
+async fn wait_established(
+    supervisor: &Supervisor,
+    command_receiver: &mut mpsc::Receiver<TransmissionCommand>,
+    state: &mut SessionState,
+) -> anyhow::Result<Option<TeamspeakConnection>> {
+    let establish = supervisor.establish();
+    tokio::pin!(establish);
+    let mut deadline = None;
+    loop {
+        if state.should_exit() {
+            return Ok(None);
+        }
+        if state.exiting {
+            deadline.get_or_insert_with(|| Instant::now() + EXIT_CONNECT_TIMEOUT);
+        }
+        tokio::select! {
+            conn = &mut establish => {
+                return conn.map(Some);
+            }
+            Some(data) = command_receiver.recv() => {
+                if matches!(data, TransmissionCommand::Data(_) | TransmissionCommand::Message(..)) {
+                    info!("Connection is not ready, message will be sent later");
                 }
+                state.handle_command(data);
+                state.update_prompt();
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                warn!("Connection is not established before exit timeout");
+                return Ok(None);
             }
         }
     }
 }
 
 async fn staff(
-    api_key: &str,
-    server: String,
-    port: u16,
-    command_sender: mpsc::Sender<TransmissionCommand>,
-    command_receiver: mpsc::Receiver<TransmissionCommand>,
+    supervisor: Supervisor,
+    mut command_receiver: mpsc::Receiver<TransmissionCommand>,
+    mut state: SessionState,
+    keepalive_interval: u64,
 ) -> anyhow::Result<()> {
-    let mut conn = TeamspeakConnection::connect(&server, port)
-        .await
-        .map_err(|e| anyhow!("Connect teamspeak error: {:?}", e))?;
-    conn.login(api_key)
-        .await
-        .map_err(|e| anyhow!("Login failure, {:?}", e))?;
-    conn.register_event().await?;
-
-    let last_transmission = Arc::new(AtomicU64::new(get_current_duration().as_secs()));
-    tokio::select! {
+    let ret = tokio::select! {
         _ = async move {
             tokio::signal::ctrl_c().await.unwrap();
             info!("Recv SIGINT again, force exit.");
@@ -122,28 +841,153 @@ async fn staff(
         } => {
             unsafe { unreachable_unchecked() }
         }
-        ret = real_staff(conn, last_transmission.clone(), command_receiver) => {
-            ret?;
-        }
-        _ = async move {
+        ret = async {
             loop {
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                if get_current_duration().as_secs() - last_transmission.load(Ordering::Relaxed) > TRANSMISSION_DEADLINE {
-                    command_sender.send(TransmissionCommand::KeepAlive).await.tap_err(|_| error!("Unable send keep alive command")).ok();
+                let Some(conn) = wait_established(&supervisor, &mut command_receiver, &mut state).await? else {
+                    return Ok(());
+                };
+
+                let last_transmission = Arc::new(AtomicU64::new(get_current_duration().as_secs()));
+                tokio::select! {
+                    ret = real_staff(conn.clone(), last_transmission.clone(), &mut command_receiver, &mut state) => {
+                        match ret {
+                            Ok(_) => return Ok(()),
+                            Err(e) => warn!("{:?}, reconnecting", e),
+                        }
+                    }
+                    _ = keep_alive_staff(conn, last_transmission, keepalive_interval) => {}
                 }
             }
-        } => {}
+        } => {
+            ret
+        }
+    };
+    for message in state.outbound.pending_messages() {
+        println!("[!] Message not sent before exit: {}", message.text());
+    }
+    ret
+}
+
+/// `Some(true)` if `--name` is given, `Some(false)` if `--no-name` is given, last one wins.
+fn flag_pair(matches: &ArgMatches, name: &str) -> Option<bool> {
+    if matches.get_flag(name) {
+        Some(true)
+    } else if matches.get_flag(&format!("no-{}", name)) {
+        Some(false)
+    } else {
+        None
     }
+}
+
+fn parse_rate(s: &str) -> anyhow::Result<f64> {
+    check_rate(f64::from_str(s)?)
+}
+
+fn check_rate(rate: f64) -> anyhow::Result<f64> {
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(anyhow!("Rate should be positive"));
+    }
+    Ok(rate)
+}
 
+fn parse_date(s: &str) -> anyhow::Result<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|e| anyhow!("Invalid date {:?}, expect YYYY-MM-DD: {}", s, e))
+}
+
+fn log_command() -> Command {
+    Command::new("log")
+        .about("Read chat log written by --log-dir")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("search").about("Search chat log").args(&[
+                arg!(--sender <NAME> "Sender nickname or uid"),
+                arg!(--server <SERVER> "Server name or unique identifier"),
+                arg!(--since <DATE> "First day to search (YYYY-MM-DD)").value_parser(parse_date),
+                arg!(--until <DATE> "Last day to search (YYYY-MM-DD)").value_parser(parse_date),
+                arg!(--mode <MODE> "Target mode (private, channel, server)")
+                    .value_parser(TargetMode::from_str),
+                arg!(--regex <PATTERN> "Regular expression matched against message")
+                    .value_parser(|s: &str| regex::Regex::new(s)),
+                arg!(--json "Output matched records as JSON Lines"),
+            ]),
+        )
+}
+
+fn run_log_search(
+    matches: &ArgMatches,
+    log_dir: Option<PathBuf>,
+    time_format: &str,
+    template: &Template,
+) -> anyhow::Result<()> {
+    let log_dir = log_dir
+        .ok_or_else(|| anyhow!("Log directory is not specified, use --log-dir or config file"))?;
+    let filter = SearchFilter {
+        sender: matches.get_one::<String>("sender").cloned(),
+        server: matches.get_one::<String>("server").cloned(),
+        since: matches.get_one("since").copied(),
+        until: matches.get_one("until").copied(),
+        mode: matches.get_one("mode").copied(),
+        pattern: matches.get_one::<regex::Regex>("regex").cloned(),
+    };
+    for record in log_search::search(&log_dir, &filter)? {
+        if matches.get_flag("json") {
+            println!("{}", serde_json::to_string(&record)?);
+        } else {
+            println!(
+                "{}",
+                log_search::format_record(&record, time_format, template, format::color_enabled())
+            );
+        }
+    }
     Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let matches = command!()
+        .subcommand(log_command())
         .args(&[
-            arg!(<API_KEY> "Teamspeak client query api key").env(DEFAULT_VARIABLE_NAME),
+            arg!([API_KEY] "Teamspeak client query api key, visible to other users, prefer --api-key-file")
+                .env(DEFAULT_VARIABLE_NAME),
+            arg!(--"api-key-file" <FILE> "Read api key from file, file should only be accessible by owner")
+                .value_parser(clap::value_parser!(PathBuf)),
+            arg!(--"api-key-stdin" "Read api key from first line of stdin")
+                .conflicts_with("api-key-file"),
+            arg!(--config <FILE> "Config file path, default is $XDG_CONFIG_HOME/teamspeak-input-helper/config.toml")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true),
+            arg!(--profile <NAME> "Use profile in config file").global(true),
             arg!(--server <SERVER> "Specify server"),
             arg!(--port <PORT> "Specify port").value_parser(clap::value_parser!(u16)),
+            arg!(--schandlerid <ID> "Pin to specified server tab instead of following current tab")
+                .value_parser(clap::value_parser!(i64)),
+            arg!(--events <EVENTS> "Comma separated events to display (enter, left, moved, poke)")
+                .value_delimiter(',')
+                .value_parser(EventKind::from_str),
+            arg!(--"message-format" <TEMPLATE> "Template of message line, placeholders: {time} {server} {channel} {sender} {uid} {mode} {msg} {own}, styles e.g. {sender:bold+auto}")
+                .value_parser(Template::from_str),
+            arg!(--"hide-own-messages" "Do not display messages sent by ourselves")
+                .overrides_with("no-hide-own-messages"),
+            arg!(--"no-hide-own-messages" "Display messages sent by ourselves")
+                .overrides_with("hide-own-messages"),
+            arg!(--"split-markers" "Prepend (1/3) markers to parts of split message")
+                .overrides_with("no-split-markers"),
+            arg!(--"no-split-markers" "Do not prepend (1/3) markers to parts of split message")
+                .overrides_with("split-markers"),
+            arg!(--burst <COUNT> "Messages can be sent in a row before rate limited")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+            arg!(--rate <RATE> "Messages allowed per second after burst is used up")
+                .value_parser(parse_rate),
+            arg!(--"history-size" <SIZE> "Maximum number of lines kept in input history")
+                .value_parser(clap::value_parser!(usize)),
+            arg!(--"history-ignore-space" "Do not record lines starting with a space in history")
+                .overrides_with("no-history-ignore-space"),
+            arg!(--"no-history-ignore-space" "Record lines starting with a space in history")
+                .overrides_with("history-ignore-space"),
+            arg!(--"no-history" "Do not save input history to disk"),
+            arg!(--"log-dir" <DIR> "Write sent and received messages to this directory as JSON Lines")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true),
             arg!(--"debug-input" "Debug input function"),
             arg!(--debug "Enable other module log output in debug/trace level"),
         ])
@@ -158,23 +1002,161 @@ fn main() -> anyhow::Result<()> {
     }
     logger_.init();
 
-    let (sender, command_receiver) = mpsc::channel(4096);
+    let config =
+        ConfigFile::load_or_default(matches.get_one::<PathBuf>("config").map(|p| p.as_path()))?;
+    let profile = config.profile(matches.get_one::<String>("profile").map(|s| s.as_str()))?;
 
-    let input_handler = InputThread::start(sender.clone());
+    let log_dir = matches
+        .get_one::<PathBuf>("log-dir")
+        .cloned()
+        .or(profile.log_dir.clone());
+    let time_format = profile
+        .time_format
+        .clone()
+        .unwrap_or_else(|| DEFAULT_TIME_FORMAT.to_string());
+    let template = matches
+        .get_one::<Template>("message-format")
+        .cloned()
+        .map(Ok)
+        .or_else(|| profile.message_format.as_deref().map(Template::from_str))
+        .transpose()?
+        .unwrap_or_default();
+    if let Some(("log", matches)) = matches.subcommand() {
+        return match matches.subcommand() {
+            Some(("search", matches)) => run_log_search(matches, log_dir, &time_format, &template),
+            _ => unreachable!(),
+        };
+    }
+
+    // Explicit flags win over positional key, which may come from environment variable
+    let api_key = if matches.get_flag("api-key-stdin") {
+        api_key::read_stdin()?
+    } else if let Some(path) = matches.get_one::<PathBuf>("api-key-file") {
+        api_key::read_file(path)?
+    } else if let Some(key) = matches.get_one::<String>("API_KEY") {
+        key.clone()
+    } else if let Some(path) = &profile.api_key_file {
+        api_key::read_file(path)?
+    } else if let Some(key) = profile.api_key {
+        // Key in config file needs same protection as key file
+        if let Some(path) = matches
+            .get_one::<PathBuf>("config")
+            .cloned()
+            .or_else(ConfigFile::default_path)
+        {
+            private_file::check(&path, "Config file containing api-key")?;
+        }
+        key
+    } else {
+        api_key::load_or_prompt()?
+    };
+    let events = match matches.get_many::<EventKind>("events") {
+        Some(events) => events.copied().collect(),
+        None => match profile.events {
+            Some(events) => events
+                .iter()
+                .map(|s| EventKind::from_str(s))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            None => EventKind::ALL.to_vec(),
+        },
+    };
+    let target = profile
+        .default_target
+        .as_deref()
+        .map(MessageTarget::from_str)
+        .transpose()?
+        .unwrap_or_default();
+    let rate = match matches.get_one::<f64>("rate") {
+        Some(rate) => *rate,
+        None => check_rate(profile.rate.unwrap_or(1.0))?,
+    };
+
+    let (sender, command_receiver) = mpsc::channel(
+        profile
+            .command_queue_size
+            .unwrap_or(DEFAULT_COMMAND_QUEUE_SIZE),
+    );
+
+    let prompt = PromptStatus::default();
+    let state = SessionState::new(
+        matches
+            .get_one("schandlerid")
+            .copied()
+            .or(profile.schandlerid),
+        prompt.clone(),
+        target,
+        DisplayOptions {
+            hide_own_messages: flag_pair(&matches, "hide-own-messages")
+                .or(profile.hide_own_messages)
+                .unwrap_or(false),
+            time_format,
+            template,
+            color: format::color_enabled(),
+            events: events.clone(),
+        },
+        flag_pair(&matches, "split-markers")
+            .or(profile.split_markers)
+            .unwrap_or(true),
+        RateLimiter::new(
+            matches
+                .get_one("burst")
+                .copied()
+                .or(profile.burst)
+                .unwrap_or(5),
+            rate,
+        ),
+        log_dir.map(ChatLogger::new),
+    );
+
+    let history = HistoryOptions {
+        path: (!matches.get_flag("no-history"))
+            .then(HistoryOptions::default_path)
+            .flatten(),
+        max_size: matches
+            .get_one("history-size")
+            .copied()
+            .or(profile.history_size)
+            .unwrap_or(1000),
+        ignore_space: flag_pair(&matches, "history-ignore-space")
+            .or(profile.history_ignore_space)
+            .unwrap_or(false),
+    };
+    let mut registry = CommandRegistry::builtin();
+    for (name, expansion) in profile.aliases {
+        registry.alias(name, expansion);
+    }
+    let input_handler = InputThread::start(
+        sender.clone(),
+        registry,
+        prompt,
+        history,
+        state.completion.clone(),
+    );
 
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap()
         .block_on(staff(
-            matches.get_one::<String>("API_KEY").unwrap(),
-            matches
-                .get_one("server")
-                .map(|s: &String| s.to_string())
-                .unwrap_or_else(|| "localhost".to_string()),
-            *matches.get_one("port").unwrap_or(&25639),
-            sender,
+            Supervisor::new(
+                matches
+                    .get_one::<String>("server")
+                    .cloned()
+                    .or(profile.server)
+                    .unwrap_or_else(|| "localhost".to_string()),
+                matches
+                    .get_one("port")
+                    .copied()
+                    .or(profile.port)
+                    .unwrap_or(25639),
+                api_key,
+                events,
+            ),
             command_receiver,
+            state,
+            profile
+                .keepalive_interval
+                .unwrap_or(DEFAULT_KEEPALIVE_INTERVAL),
         ))?;
 
     if input_handler.alive() {
@@ -185,3 +1167,113 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        handle_notification, real_staff, refresh_model, refresh_tabs, DisplayOptions, SessionState,
+    };
+    use crate::datastructures::{EventKind, MessageTarget, TransmissionCommand};
+    use crate::mock_server::{MockServer, MOCK_CLIENT_ID, MOCK_SERVER_ID};
+    use crate::rate_limit::RateLimiter;
+    use crate::tslib::TeamspeakConnection;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+    use tokio::time::Duration;
+
+    async fn connect(server: &MockServer) -> TeamspeakConnection {
+        let conn = TeamspeakConnection::connect("127.0.0.1", server.port())
+            .await
+            .unwrap();
+        conn.login("mock key").await.unwrap();
+        conn
+    }
+
+    fn new_state(limiter: RateLimiter) -> SessionState {
+        SessionState::new(
+            None,
+            Default::default(),
+            MessageTarget::Channel,
+            DisplayOptions {
+                hide_own_messages: false,
+                time_format: super::DEFAULT_TIME_FORMAT.to_string(),
+                template: Default::default(),
+                color: false,
+                events: EventKind::ALL.to_vec(),
+            },
+            true,
+            limiter,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_batched_move_updates_model() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+        let mut state = new_state(RateLimiter::new(5, 1.0));
+        refresh_tabs(&conn, &mut state.tabs).await.unwrap();
+        server.reply_next(
+            "clientlist",
+            &[&format!(
+                "clid=3 cid=1 client_nickname=Alice client_type=0|clid=4 cid=1 client_nickname=Bob client_type=0|clid={} cid=1 client_nickname=Me client_type=0",
+                MOCK_CLIENT_ID
+            )],
+        );
+        refresh_model(&conn, &mut state).await;
+
+        let mut notify = conn.subscribe();
+        for line in [
+            "notifyclientmoved schandlerid=1 ctid=2 reasonid=0 clid=3|clid=4",
+            "notifyclientmoved schandlerid=broken",
+        ] {
+            server.inject(line);
+            let line = tokio::time::timeout(Duration::from_secs(5), notify.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            handle_notification(&conn, &mut state, &line).await;
+        }
+
+        let tables = state.model.server(MOCK_SERVER_ID).unwrap();
+        let channel_of = |client_id| tables.channel_of(client_id).unwrap().name().to_string();
+        assert_eq!(channel_of(3), "Away From Keyboard");
+        assert_eq!(channel_of(4), "Away From Keyboard");
+        assert_eq!(channel_of(MOCK_CLIENT_ID), "Lobby");
+        assert!(!conn.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_terminate_sends_remaining() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+        let mut state = new_state(RateLimiter::new(5, 20.0));
+        let (sender, mut receiver) = mpsc::channel(16);
+        // More lines than burst, then EOF of scripted stdin
+        for n in 0..8 {
+            sender
+                .send(TransmissionCommand::Data(format!("line {}", n)))
+                .await
+                .unwrap();
+        }
+        sender.send(TransmissionCommand::Terminate).await.unwrap();
+        drop(sender);
+
+        let last_transmission = Arc::new(AtomicU64::new(0));
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            real_staff(conn, last_transmission, &mut receiver, &mut state),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let sent = server
+            .received()
+            .iter()
+            .filter(|line| line.starts_with("sendtextmessage"))
+            .count();
+        assert_eq!(sent, 8);
+        assert!(state.outbound.is_settled());
+    }
+}
@@ -0,0 +1,249 @@
+mod inner {
+    use crate::escape::escape;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::broadcast;
+    use tokio::task::JoinHandle;
+
+    pub const MOCK_SERVER_ID: i64 = 1;
+    pub const MOCK_CLIENT_ID: i64 = 5;
+    pub const MOCK_NICKNAME: &str = "Mock User";
+    pub const MOCK_SERVER_NAME: &str = "Mock Server";
+    /// Contains characters which are not allowed in file name
+    pub const MOCK_SERVER_UID: &str = "mock+server/uid=";
+
+    const WELCOME: &[&str] = &[
+        "TS3 Client",
+        "Welcome to the TeamSpeak 3 ClientQuery interface, type \"help\" for a list of commands and \"help <command>\" for information on a specific command.",
+        "Use the \"auth\" command to authenticate yourself. See \"help auth\" for details.",
+        "selected schandlerid=1",
+    ];
+
+    #[derive(Debug, Default)]
+    struct Script {
+        received: Vec<String>,
+        /// Lines returned instead of normal response, keyed by command name
+        replies: HashMap<String, VecDeque<Vec<String>>>,
+    }
+
+    #[derive(Clone, Debug)]
+    enum Injection {
+        Line(String),
+        Disconnect,
+    }
+
+    /// Fake ClientQuery server on a local TCP port, used by tests.
+    pub struct MockServer {
+        port: u16,
+        script: Arc<Mutex<Script>>,
+        injection: broadcast::Sender<Injection>,
+        handle: JoinHandle<()>,
+    }
+
+    impl MockServer {
+        pub async fn start(api_key: &str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let script = Arc::new(Mutex::new(Script::default()));
+            let injection = broadcast::channel(16).0;
+            let handle = tokio::spawn({
+                let script = script.clone();
+                let injection = injection.clone();
+                let api_key = api_key.to_string();
+                async move {
+                    while let Ok((stream, _)) = listener.accept().await {
+                        tokio::spawn(Self::serve(
+                            stream,
+                            api_key.clone(),
+                            script.clone(),
+                            injection.subscribe(),
+                        ));
+                    }
+                }
+            });
+            Self {
+                port,
+                script,
+                injection,
+                handle,
+            }
+        }
+
+        pub fn port(&self) -> u16 {
+            self.port
+        }
+
+        /// Commands received from all connections, in order.
+        pub fn received(&self) -> Vec<String> {
+            self.script.lock().unwrap().received.clone()
+        }
+
+        fn script_next(&self, command: &str, lines: Vec<String>) {
+            self.script
+                .lock()
+                .unwrap()
+                .replies
+                .entry(command.to_string())
+                .or_default()
+                .push_back(lines);
+        }
+
+        /// Reply error to next `command` instead of normal response.
+        pub fn fail_next(&self, command: &str, code: i32, msg: &str) {
+            self.script_next(command, vec![Self::status(code, msg)]);
+        }
+
+        /// Reply `lines` and ok status to next `command` instead of normal response.
+        pub fn reply_next(&self, command: &str, lines: &[&str]) {
+            let mut lines = lines.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            lines.push(Self::status(0, "ok"));
+            self.script_next(command, lines);
+        }
+
+        /// Send line to every connected client.
+        pub fn inject(&self, line: &str) {
+            self.injection.send(Injection::Line(line.to_string())).ok();
+        }
+
+        /// Close every connection, server keeps accepting new ones.
+        pub fn disconnect_all(&self) {
+            self.injection.send(Injection::Disconnect).ok();
+        }
+
+        fn status(code: i32, msg: &str) -> String {
+            format!("error id={} msg={}", code, escape(msg))
+        }
+
+        fn respond(
+            script: &Mutex<Script>,
+            api_key: &str,
+            authorized: &mut bool,
+            line: &str,
+        ) -> Vec<String> {
+            let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+            let params: HashMap<&str, &str> = args
+                .split(' ')
+                .filter_map(|arg| arg.split_once('='))
+                .collect();
+            let mut script = script.lock().unwrap();
+            script.received.push(line.to_string());
+            if let Some(lines) = script
+                .replies
+                .get_mut(command)
+                .and_then(|replies| replies.pop_front())
+            {
+                return lines;
+            }
+
+            if command.eq("auth") {
+                if params.get("apikey").copied() != Some(&escape(api_key)) {
+                    return vec![Self::status(1538, "invalid parameter")];
+                }
+                *authorized = true;
+                return vec![Self::status(0, "ok")];
+            }
+            if !*authorized {
+                return vec![Self::status(1796, "currently not possible")];
+            }
+
+            let mut lines = match command {
+                "whoami" => vec![format!("clid={} cid=1", MOCK_CLIENT_ID)],
+                "currentschandlerid" | "serverconnectionhandlerlist" => {
+                    vec![format!("schandlerid={}", MOCK_SERVER_ID)]
+                }
+                "servervariable" => {
+                    vec![format!(
+                        "virtualserver_name={} virtualserver_unique_identifier={}",
+                        escape(MOCK_SERVER_NAME),
+                        escape(MOCK_SERVER_UID)
+                    )]
+                }
+                "clientlist" => vec![format!(
+                    "clid={} cid=1 client_database_id=1 client_nickname={} client_type=0 client_unique_identifier=mockuid= client_away=0 client_away_message client_flag_talking=0 client_input_muted=0 client_output_muted=0",
+                    MOCK_CLIENT_ID,
+                    escape(MOCK_NICKNAME)
+                )],
+                "channellist" => vec![
+                    "cid=1 pid=0 channel_order=0 channel_name=Lobby total_clients=1|cid=2 pid=1 channel_order=0 channel_name=Away\\sFrom\\sKeyboard total_clients=0".to_string(),
+                ],
+                "clientnotifyregister" | "clientmove" | "clientupdate" | "clientpoke" => vec![],
+                "sendtextmessage" => {
+                    let mut lines = vec![Self::status(0, "ok")];
+                    lines.push(format!(
+                        "notifytextmessage schandlerid={} targetmode={} msg={} invokerid={} invokername={} invokeruid=mockuid=",
+                        params.get("schandlerid").unwrap_or(&"1"),
+                        params.get("targetmode").unwrap_or(&"2"),
+                        params.get("msg").unwrap_or(&""),
+                        MOCK_CLIENT_ID,
+                        escape(MOCK_NICKNAME)
+                    ));
+                    return lines;
+                }
+                _ => return vec![Self::status(256, "command not found")],
+            };
+            lines.push(Self::status(0, "ok"));
+            lines
+        }
+
+        async fn serve(
+            stream: TcpStream,
+            api_key: String,
+            script: Arc<Mutex<Script>>,
+            mut injection: broadcast::Receiver<Injection>,
+        ) {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader).lines();
+            let mut authorized = false;
+            for line in WELCOME {
+                if writer
+                    .write_all(format!("{}\n\r", line).as_bytes())
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            loop {
+                let lines = tokio::select! {
+                    line = reader.next_line() => {
+                        let Ok(Some(line)) = line else {
+                            return;
+                        };
+                        // Client separates commands by "\n\r"
+                        let line = line.trim_matches('\r');
+                        if line.is_empty() {
+                            continue;
+                        }
+                        Self::respond(&script, &api_key, &mut authorized, line)
+                    }
+                    injected = injection.recv() => match injected {
+                        Ok(Injection::Line(line)) => vec![line],
+                        Ok(Injection::Disconnect) | Err(_) => return,
+                    },
+                };
+                for line in lines {
+                    if writer
+                        .write_all(format!("{}\n\r", line).as_bytes())
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    impl Drop for MockServer {
+        fn drop(&mut self) {
+            self.handle.abort();
+            self.disconnect_all();
+        }
+    }
+}
+
+pub use inner::{
+    MockServer, MOCK_CLIENT_ID, MOCK_NICKNAME, MOCK_SERVER_ID, MOCK_SERVER_NAME, MOCK_SERVER_UID,
+};
@@ -0,0 +1,343 @@
+mod inner {
+    use crate::datastructures::MessageTarget;
+    use std::collections::VecDeque;
+    use tokio::time::{Duration, Instant};
+
+    const MAX_ATTEMPTS: u32 = 5;
+    const RETRY_DELAY: Duration = Duration::from_secs(3);
+    const CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub enum DeliveryState {
+        /// Waiting to be sent, or waiting for retry
+        Pending,
+        /// Server accepted command, waiting echoed `notifytextmessage`
+        Sent,
+        Confirmed,
+        Failed,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct OutboundMessage {
+        id: u64,
+        server_id: Option<i64>,
+        target: MessageTarget,
+        text: String,
+        state: DeliveryState,
+        attempts: u32,
+        not_before: Option<Instant>,
+        sent_at: Option<Instant>,
+        last_error: Option<String>,
+        /// Previous part of a split message, must be confirmed before this one is sent
+        after: Option<u64>,
+    }
+
+    impl OutboundMessage {
+        fn new(id: u64, server_id: Option<i64>, target: MessageTarget, text: String) -> Self {
+            Self {
+                id,
+                server_id,
+                target,
+                text,
+                state: DeliveryState::Pending,
+                attempts: 0,
+                not_before: None,
+                sent_at: None,
+                last_error: None,
+                after: None,
+            }
+        }
+
+        pub fn id(&self) -> u64 {
+            self.id
+        }
+        /// Server tab bound when message is typed, `None` if it is unknown at that time.
+        pub fn server_id(&self) -> Option<i64> {
+            self.server_id
+        }
+        pub fn target(&self) -> &MessageTarget {
+            &self.target
+        }
+        pub fn text(&self) -> &str {
+            &self.text
+        }
+        pub fn state(&self) -> DeliveryState {
+            self.state
+        }
+        pub fn last_error(&self) -> Option<&str> {
+            self.last_error.as_deref()
+        }
+    }
+
+    /// Messages typed by user, kept in order until delivery is confirmed or failed.
+    ///
+    /// Queue is kept in memory and outlives connection, so messages survive reconnecting
+    /// but not restarting, messages still pending at exit are reported to user.
+    #[derive(Debug, Default)]
+    pub struct OutboundQueue {
+        messages: VecDeque<OutboundMessage>,
+        next_id: u64,
+    }
+
+    impl OutboundQueue {
+        pub fn push(&mut self, server_id: Option<i64>, target: MessageTarget, text: String) -> u64 {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.messages
+                .push_back(OutboundMessage::new(id, server_id, target, text));
+            id
+        }
+
+        /// Push parts of a split message, each part is sent after previous one is confirmed.
+        pub fn push_parts(
+            &mut self,
+            server_id: Option<i64>,
+            target: MessageTarget,
+            parts: Vec<String>,
+        ) {
+            let mut previous = None;
+            for part in parts {
+                let id = self.push(server_id, target.clone(), part);
+                if let Some(message) = self.get_mut(id) {
+                    message.after = previous;
+                }
+                previous = Some(id);
+            }
+        }
+
+        fn is_waiting(&self, message: &OutboundMessage) -> bool {
+            message.after.is_some_and(|after| {
+                self.messages
+                    .iter()
+                    .any(|m| m.id == after && m.state != DeliveryState::Confirmed)
+            })
+        }
+
+        /// Mark remain parts of split message as failed.
+        fn fail_following(&mut self, id: u64) {
+            let mut failed = id;
+            while let Some(message) = self.messages.iter_mut().find(|m| m.after == Some(failed)) {
+                message.state = DeliveryState::Failed;
+                message.last_error = Some("Previous part is not delivered".to_string());
+                failed = message.id;
+            }
+        }
+
+        fn get_mut(&mut self, id: u64) -> Option<&mut OutboundMessage> {
+            self.messages.iter_mut().find(|m| m.id == id)
+        }
+
+        /// Return first pending message if it is ready to send.
+        ///
+        /// Later messages will not be sent before earlier one, to keep order.
+        pub fn next_ready(&self, now: Instant) -> Option<&OutboundMessage> {
+            self.messages
+                .iter()
+                .find(|m| m.state == DeliveryState::Pending)
+                .filter(|m| m.not_before.is_none_or(|t| t <= now) && !self.is_waiting(m))
+        }
+
+        pub fn mark_sent(&mut self, id: u64) {
+            if let Some(message) = self.get_mut(id) {
+                message.attempts += 1;
+                message.state = DeliveryState::Sent;
+                message.sent_at = Some(Instant::now());
+                message.last_error = None;
+            }
+        }
+
+        /// Retry message after delay, mark it failed if too many attempts.
+        ///
+        /// Return `false` if message will not be retried.
+        pub fn retry_later(&mut self, id: u64, error: String) -> bool {
+            let Some(message) = self.get_mut(id) else {
+                return false;
+            };
+            message.attempts += 1;
+            message.last_error = Some(error);
+            if message.attempts >= MAX_ATTEMPTS {
+                message.state = DeliveryState::Failed;
+                self.fail_following(id);
+                return false;
+            }
+            message.not_before = Some(Instant::now() + RETRY_DELAY * message.attempts);
+            true
+        }
+
+        pub fn mark_failed(&mut self, id: u64, error: String) {
+            if let Some(message) = self.get_mut(id) {
+                message.attempts += 1;
+                message.state = DeliveryState::Failed;
+                message.last_error = Some(error);
+                self.fail_following(id);
+            }
+        }
+
+        /// Confirm oldest sent message which has same content as echoed message.
+        pub fn confirm(&mut self, text: &str) -> Option<u64> {
+            let message = self
+                .messages
+                .iter_mut()
+                .find(|m| m.state == DeliveryState::Sent && m.text.eq(text))?;
+            message.state = DeliveryState::Confirmed;
+            Some(message.id)
+        }
+
+        /// Remove confirmed and failed messages from queue.
+        ///
+        /// Sent messages without echo in time are treated as confirmed, since server already accepted it.
+        pub fn drain_finished(&mut self, now: Instant) -> Vec<OutboundMessage> {
+            for message in self.messages.iter_mut() {
+                if message.state == DeliveryState::Sent
+                    && message.sent_at.is_some_and(|t| now - t >= CONFIRM_TIMEOUT)
+                {
+                    message.state = DeliveryState::Confirmed;
+                    message.last_error = Some("Echo message not received".to_string());
+                }
+            }
+            let mut finished = Vec::new();
+            self.messages.retain(|m| {
+                if matches!(m.state, DeliveryState::Confirmed | DeliveryState::Failed) {
+                    finished.push(m.clone());
+                    return false;
+                }
+                true
+            });
+            finished
+        }
+
+        /// Every message is confirmed or failed, nothing is waiting to be sent or echoed.
+        pub fn is_settled(&self) -> bool {
+            self.messages
+                .iter()
+                .all(|m| matches!(m.state, DeliveryState::Confirmed | DeliveryState::Failed))
+        }
+
+        /// Messages not yet sent to server.
+        pub fn pending_messages(&self) -> impl Iterator<Item = &OutboundMessage> {
+            self.messages
+                .iter()
+                .filter(|m| m.state == DeliveryState::Pending)
+        }
+
+        pub fn pending(&self) -> usize {
+            self.pending_messages().count()
+        }
+
+        /// Next time point which queue should be checked again.
+        pub fn next_wakeup(&self) -> Option<Instant> {
+            self.messages
+                .iter()
+                .filter_map(|m| match m.state {
+                    DeliveryState::Pending => m.not_before,
+                    DeliveryState::Sent => m.sent_at.map(|t| t + CONFIRM_TIMEOUT),
+                    _ => None,
+                })
+                .min()
+        }
+    }
+}
+
+pub use inner::{DeliveryState, OutboundMessage, OutboundQueue};
+
+#[cfg(test)]
+mod test {
+    use super::{DeliveryState, OutboundQueue};
+    use crate::datastructures::MessageTarget;
+    use tokio::time::{Duration, Instant};
+
+    fn states(queue: &mut OutboundQueue, now: Instant) -> Vec<(String, DeliveryState)> {
+        queue
+            .drain_finished(now)
+            .into_iter()
+            .map(|m| (m.text().to_string(), m.state()))
+            .collect()
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut queue = OutboundQueue::default();
+        let id = queue.push(None, MessageTarget::Channel, "hello".to_string());
+        let now = Instant::now();
+        assert_eq!(queue.next_ready(now).unwrap().id(), id);
+        assert_eq!(queue.pending(), 1);
+
+        queue.mark_sent(id);
+        assert!(queue.next_ready(now).is_none());
+        assert_eq!(queue.pending(), 0);
+        assert!(states(&mut queue, now).is_empty());
+        assert_eq!(queue.confirm("other"), None);
+        assert_eq!(queue.confirm("hello"), Some(id));
+        assert_eq!(
+            states(&mut queue, now),
+            [("hello".to_string(), DeliveryState::Confirmed)]
+        );
+        assert!(queue.next_wakeup().is_none());
+    }
+
+    #[test]
+    fn test_confirm_timeout() {
+        let mut queue = OutboundQueue::default();
+        let id = queue.push(None, MessageTarget::Channel, "hello".to_string());
+        queue.mark_sent(id);
+        let wakeup = queue.next_wakeup().unwrap();
+        assert!(states(&mut queue, wakeup - Duration::from_millis(1)).is_empty());
+        let finished = queue.drain_finished(wakeup);
+        assert_eq!(finished[0].state(), DeliveryState::Confirmed);
+        assert!(finished[0].last_error().is_some());
+    }
+
+    #[test]
+    fn test_retry_later() {
+        let mut queue = OutboundQueue::default();
+        let first = queue.push(None, MessageTarget::Channel, "first".to_string());
+        queue.push(None, MessageTarget::Server, "second".to_string());
+
+        let mut previous_delay = Duration::ZERO;
+        for _ in 1..5 {
+            let now = Instant::now();
+            assert!(queue.retry_later(first, "flooding".to_string()));
+            // Later message is not sent before earlier one
+            assert!(queue.next_ready(now).is_none());
+            let delay = queue.next_wakeup().unwrap() - now;
+            assert!(delay > previous_delay);
+            previous_delay = delay;
+            assert_eq!(queue.next_ready(now + delay).map(|m| m.id()), Some(first));
+        }
+        assert!(!queue.retry_later(first, "flooding".to_string()));
+        let now = Instant::now();
+        let finished = queue.drain_finished(now);
+        assert_eq!(finished[0].state(), DeliveryState::Failed);
+        assert_eq!(finished[0].last_error(), Some("flooding"));
+        assert_eq!(queue.next_ready(now).unwrap().text(), "second");
+    }
+
+    #[test]
+    fn test_split_parts() {
+        let mut queue = OutboundQueue::default();
+        queue.push_parts(
+            Some(1),
+            MessageTarget::Channel,
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        );
+        let now = Instant::now();
+        let first = queue.next_ready(now).unwrap().id();
+        queue.mark_sent(first);
+        // Next part waits until previous one is confirmed
+        assert!(queue.next_ready(now).is_none());
+        queue.confirm("a");
+        let second = queue.next_ready(now).unwrap();
+        assert_eq!((second.text(), second.server_id()), ("b", Some(1)));
+
+        queue.mark_failed(second.id(), "invalid target".to_string());
+        assert_eq!(
+            states(&mut queue, now),
+            [
+                ("a".to_string(), DeliveryState::Confirmed),
+                ("b".to_string(), DeliveryState::Failed),
+                ("c".to_string(), DeliveryState::Failed),
+            ]
+        );
+        assert_eq!(queue.pending(), 0);
+    }
+}
@@ -0,0 +1,64 @@
+mod inner {
+    use std::fs::OpenOptions;
+    use std::path::Path;
+
+    /// Refuse file which can be accessed by other users.
+    #[cfg(unix)]
+    pub fn check(path: &Path, description: &str) -> anyhow::Result<()> {
+        use anyhow::anyhow;
+        use std::os::unix::fs::PermissionsExt;
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| anyhow!("Unable read {} {}: {}", description, path.display(), e))?;
+        let mode = metadata.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(anyhow!(
+                "{} {} is accessible by other users (mode {:o}), run chmod 600 to fix",
+                description,
+                path.display(),
+                mode & 0o777
+            ));
+        }
+        Ok(())
+    }
+
+    /// Permission bits are not available on this platform, nothing to check.
+    #[cfg(not(unix))]
+    pub fn check(_path: &Path, _description: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Options for creating file only readable by owner.
+    pub fn options() -> OpenOptions {
+        #[allow(unused_mut)]
+        let mut options = OpenOptions::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+    }
+}
+
+pub use inner::{check, options};
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::{check, options};
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        options().write(true).create(true).open(&path).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(check(&path, "file").is_ok());
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(check(&path, "file").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+mod inner {
+    use tokio::time::{Duration, Instant};
+
+    /// Refill rate will not be lower than this after backing off
+    const MIN_REFILL_FACTOR: f64 = 0.125;
+    /// Refill rate restored after each successful send
+    const RECOVER_FACTOR: f64 = 1.1;
+
+    /// Token bucket which limits how fast messages are sent.
+    ///
+    /// Refill rate is reduced each time server reports flooding, and restored slowly after that.
+    #[derive(Clone, Debug)]
+    pub struct RateLimiter {
+        burst: f64,
+        refill: f64,
+        factor: f64,
+        tokens: f64,
+        last_refill: Instant,
+    }
+
+    impl RateLimiter {
+        pub fn new(burst: u32, refill: f64) -> Self {
+            Self {
+                burst: burst.max(1) as f64,
+                refill,
+                factor: 1.0,
+                tokens: burst.max(1) as f64,
+                last_refill: Instant::now(),
+            }
+        }
+
+        fn rate(&self) -> f64 {
+            self.refill * self.factor
+        }
+
+        fn refill(&mut self, now: Instant) {
+            let elapsed = now
+                .saturating_duration_since(self.last_refill)
+                .as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate()).min(self.burst);
+            self.last_refill = now;
+        }
+
+        /// Take a token if available.
+        pub fn try_acquire(&mut self, now: Instant) -> bool {
+            self.refill(now);
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                return true;
+            }
+            false
+        }
+
+        /// Time point which next token will be available.
+        pub fn next_available(&self, now: Instant) -> Instant {
+            if self.tokens >= 1.0 {
+                return now;
+            }
+            now + Duration::from_secs_f64((1.0 - self.tokens) / self.rate())
+        }
+
+        /// Server reports flooding, drain bucket and halve refill rate.
+        pub fn on_flood(&mut self, now: Instant) {
+            self.refill(now);
+            self.tokens = 0.0;
+            self.factor = (self.factor / 2.0).max(MIN_REFILL_FACTOR);
+        }
+
+        pub fn on_success(&mut self) {
+            self.factor = (self.factor * RECOVER_FACTOR).min(1.0);
+        }
+    }
+}
+
+pub use inner::RateLimiter;
+
+#[cfg(test)]
+mod test {
+    use super::RateLimiter;
+    use tokio::time::{Duration, Instant};
+
+    #[test]
+    fn test_burst_and_refill() {
+        let mut limiter = RateLimiter::new(2, 1.0);
+        let now = Instant::now();
+        assert!(limiter.try_acquire(now));
+        assert!(limiter.try_acquire(now));
+        assert!(!limiter.try_acquire(now));
+        assert!(limiter.next_available(now) > now);
+        assert!(limiter.try_acquire(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_back_off_on_flood() {
+        let mut limiter = RateLimiter::new(5, 1.0);
+        let now = Instant::now();
+        limiter.on_flood(now);
+        assert!(!limiter.try_acquire(now + Duration::from_secs(1)));
+        assert!(limiter.try_acquire(now + Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,229 @@
+mod inner {
+    use std::collections::BTreeMap;
+    use std::fmt::Write;
+
+    #[derive(Clone, Debug, Default)]
+    struct ServerTab {
+        name: Option<String>,
+        unique_id: Option<String>,
+        connected: bool,
+        client_id: Option<i64>,
+    }
+
+    /// Track every server tab of client, and which one should receive messages.
+    #[derive(Clone, Debug)]
+    pub struct ServerTabs {
+        tabs: BTreeMap<i64, ServerTab>,
+        current: i64,
+        pinned: Option<i64>,
+    }
+
+    impl Default for ServerTabs {
+        fn default() -> Self {
+            Self {
+                tabs: Default::default(),
+                current: 1,
+                pinned: None,
+            }
+        }
+    }
+
+    impl ServerTabs {
+        pub fn new(pinned: Option<i64>) -> Self {
+            Self {
+                pinned,
+                ..Default::default()
+            }
+        }
+
+        /// Tab which should receive messages, pinned tab is preferred.
+        pub fn active(&self) -> i64 {
+            self.pinned.unwrap_or(self.current)
+        }
+
+        pub fn pinned(&self) -> Option<i64> {
+            self.pinned
+        }
+
+        pub fn set_pinned(&mut self, pinned: Option<i64>) {
+            self.pinned = pinned;
+        }
+
+        pub fn set_current(&mut self, server_id: i64) {
+            self.current = server_id;
+            self.tabs.entry(server_id).or_default();
+        }
+
+        /// Replace tab list, information of tabs still exist will be kept.
+        pub fn reset(&mut self, server_ids: &[i64]) {
+            self.tabs.retain(|id, _| server_ids.contains(id));
+            for id in server_ids {
+                self.tabs.entry(*id).or_default();
+            }
+        }
+
+        pub fn update(&mut self, server_id: i64, name: Option<String>) {
+            let tab = self.tabs.entry(server_id).or_default();
+            tab.connected = name.is_some();
+            tab.name = name;
+            if !tab.connected {
+                tab.client_id = None;
+                tab.unique_id = None;
+            }
+        }
+
+        pub fn set_unique_id(&mut self, server_id: i64, unique_id: String) {
+            self.tabs.entry(server_id).or_default().unique_id =
+                Some(unique_id).filter(|id| !id.is_empty());
+        }
+
+        /// Unique identifier of server connected in tab, from `servervariable`.
+        pub fn unique_id(&self, server_id: i64) -> Option<&str> {
+            self.tabs
+                .get(&server_id)
+                .and_then(|tab| tab.unique_id.as_deref())
+        }
+
+        pub fn set_client_id(&mut self, server_id: i64, client_id: i64) {
+            self.tabs.entry(server_id).or_default().client_id = Some(client_id);
+        }
+
+        /// Our own client id in specified tab, from `whoami`.
+        pub fn client_id(&self, server_id: i64) -> Option<i64> {
+            self.tabs.get(&server_id).and_then(|tab| tab.client_id)
+        }
+
+        pub fn set_disconnected(&mut self, server_id: i64) {
+            self.update(server_id, None);
+        }
+
+        pub fn contains(&self, server_id: i64) -> bool {
+            self.tabs.contains_key(&server_id)
+        }
+
+        pub fn connected(&self) -> Vec<i64> {
+            self.tabs
+                .iter()
+                .filter(|(_, tab)| tab.connected)
+                .map(|(id, _)| *id)
+                .collect()
+        }
+
+        pub fn name(&self, server_id: i64) -> Option<&str> {
+            self.tabs
+                .get(&server_id)
+                .and_then(|tab| tab.name.as_deref())
+        }
+
+        /// Server name, or tab id if name is unknown.
+        pub fn label(&self, server_id: i64) -> String {
+            self.name(server_id)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("tab {}", server_id))
+        }
+
+        pub fn active_name(&self) -> Option<&str> {
+            self.name(self.active())
+        }
+
+        pub fn describe(&self) -> String {
+            let mut s = String::new();
+            for (id, tab) in &self.tabs {
+                write!(
+                    s,
+                    "{mark} {id}: {name}{status}",
+                    mark = if *id == self.active() { "*" } else { " " },
+                    id = id,
+                    name = tab.name.as_deref().unwrap_or("<unknown>"),
+                    status = if tab.connected { "" } else { " (disconnected)" },
+                )
+                .unwrap();
+                if self.pinned == Some(*id) {
+                    s.push_str(" (pinned)");
+                }
+                s.push('\n');
+            }
+            s.push_str(if self.pinned.is_some() {
+                "Mode: pinned"
+            } else {
+                "Mode: follow current tab"
+            });
+            s
+        }
+    }
+}
+
+pub use inner::ServerTabs;
+
+#[cfg(test)]
+mod test {
+    use super::ServerTabs;
+
+    fn tabs() -> ServerTabs {
+        let mut tabs = ServerTabs::new(None);
+        tabs.reset(&[1, 2]);
+        tabs.update(1, Some("Alpha".to_string()));
+        tabs.set_client_id(1, 5);
+        tabs.set_unique_id(1, "alpha=".to_string());
+        tabs.update(2, Some("Beta".to_string()));
+        tabs.set_current(1);
+        tabs
+    }
+
+    #[test]
+    fn test_active() {
+        let mut tabs = tabs();
+        assert_eq!(tabs.active(), 1);
+        tabs.set_current(2);
+        assert_eq!(tabs.active(), 2);
+        assert_eq!(tabs.active_name(), Some("Beta"));
+
+        tabs.set_pinned(Some(1));
+        assert_eq!(tabs.active(), 1);
+        // Pinned tab is kept when current tab changes
+        tabs.set_current(2);
+        assert_eq!(tabs.active(), 1);
+
+        tabs.set_pinned(None);
+        assert_eq!(tabs.active(), 2);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut tabs = tabs();
+        tabs.reset(&[1, 3]);
+        assert!(!tabs.contains(2));
+        assert!(tabs.contains(3));
+        assert_eq!(tabs.name(1), Some("Alpha"));
+        assert_eq!(tabs.client_id(1), Some(5));
+        assert_eq!(tabs.unique_id(1), Some("alpha="));
+        assert_eq!(tabs.label(3), "tab 3");
+        assert_eq!(tabs.connected(), [1]);
+    }
+
+    #[test]
+    fn test_set_disconnected() {
+        let mut tabs = tabs();
+        tabs.set_disconnected(1);
+        assert_eq!(tabs.client_id(1), None);
+        assert_eq!(tabs.unique_id(1), None);
+        assert_eq!(tabs.connected(), [2]);
+        assert!(tabs.contains(1));
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut tabs = tabs();
+        tabs.set_disconnected(2);
+        tabs.set_pinned(Some(2));
+        assert_eq!(
+            tabs.describe(),
+            "  1: Alpha\n* 2: <unknown> (disconnected) (pinned)\nMode: pinned"
+        );
+        tabs.set_pinned(None);
+        assert_eq!(
+            tabs.describe(),
+            "* 1: Alpha\n  2: <unknown> (disconnected)\nMode: follow current tab"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+mod inner {
+    use crate::escape::escape;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    /// Maximum length of text message after escaping
+    pub const MAX_MESSAGE_LENGTH: usize = 8192;
+
+    fn escaped_len(s: &str) -> usize {
+        escape(s).len()
+    }
+
+    fn marker(index: usize, total: usize) -> String {
+        format!("({}/{}) ", index, total)
+    }
+
+    /// Split text into parts whose escaped length are not longer than limit.
+    ///
+    /// Text is split at whitespace if possible, otherwise at grapheme boundary,
+    /// so multi-byte characters will never be broken.
+    fn split_parts(text: &str, limit: usize) -> Vec<&str> {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        let mut length = 0;
+        // Byte offset after last whitespace in current part
+        let mut last_break = None;
+
+        for (offset, grapheme) in text.grapheme_indices(true) {
+            let grapheme_length = escaped_len(grapheme);
+            if length + grapheme_length > limit && offset > start {
+                let end = last_break.unwrap_or(offset);
+                parts.push(&text[start..end]);
+                length = escaped_len(&text[end..offset]);
+                start = end;
+                last_break = None;
+            }
+            length += grapheme_length;
+            if grapheme.chars().all(char::is_whitespace) {
+                last_break = Some(offset + grapheme.len());
+            }
+        }
+        if start < text.len() {
+            parts.push(&text[start..]);
+        }
+        parts
+    }
+
+    /// Split message if it is too long, prepend `(1/3) ` marker to each part if requested.
+    pub fn split_message(text: &str, limit: usize, markers: bool) -> Vec<String> {
+        if escaped_len(text) <= limit {
+            return vec![text.to_string()];
+        }
+        if !markers {
+            return split_parts(text, limit)
+                .into_iter()
+                .map(|s| s.to_string())
+                .collect();
+        }
+
+        // Part count affects marker length, so retry until marker is long enough
+        let mut total = 9;
+        loop {
+            let reserved = escaped_len(&marker(total, total));
+            let parts = split_parts(text, limit - reserved);
+            if parts.len() <= total {
+                let count = parts.len();
+                return parts
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, part)| format!("{}{}", marker(index + 1, count), part))
+                    .collect();
+            }
+            total = total * 10 + 9;
+        }
+    }
+}
+
+pub use inner::{split_message, MAX_MESSAGE_LENGTH};
+
+#[cfg(test)]
+mod test {
+    use super::split_message;
+    use crate::escape::escape;
+
+    #[test]
+    fn test_short_message() {
+        assert_eq!(split_message("hello world", 100, true), vec!["hello world"]);
+    }
+
+    #[test]
+    fn test_split_at_whitespace() {
+        assert_eq!(
+            split_message("aaaa bbbb cccc", 9, false),
+            vec!["aaaa ", "bbbb ", "cccc"]
+        );
+    }
+
+    #[test]
+    fn test_split_cjk() {
+        let text = "你好世界".repeat(10);
+        let parts = split_message(&text, 16, false);
+        assert!(parts.iter().all(|part| escape(part).len() <= 16));
+        assert_eq!(parts.concat(), text);
+    }
+
+    #[test]
+    fn test_split_with_markers() {
+        let text = "word ".repeat(100);
+        let parts = split_message(&text, 64, true);
+        let total = parts.len();
+        for (index, part) in parts.iter().enumerate() {
+            assert!(part.starts_with(&format!("({}/{}) ", index + 1, total)));
+            assert!(escape(part).len() <= 64);
+        }
+    }
+}
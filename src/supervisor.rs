@@ -0,0 +1,142 @@
+mod inner {
+    use crate::datastructures::EventKind;
+    use crate::tslib::TeamspeakConnection;
+    use anyhow::anyhow;
+    use log::{info, warn};
+    use std::fmt::{Debug, Formatter};
+    use tokio::time::Duration;
+
+    const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+    enum EstablishError {
+        Fatal(anyhow::Error),
+        Retry(String),
+    }
+
+    /// Establish ClientQuery connection, retry with exponential backoff if link is not available.
+    #[derive(Clone)]
+    pub struct Supervisor {
+        server: String,
+        port: u16,
+        api_key: String,
+        events: Vec<EventKind>,
+    }
+
+    impl Debug for Supervisor {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Supervisor")
+                .field("server", &self.server)
+                .field("port", &self.port)
+                .field("api_key", &"<redacted>")
+                .field("events", &self.events)
+                .finish()
+        }
+    }
+
+    impl Supervisor {
+        pub fn new(server: String, port: u16, api_key: String, events: Vec<EventKind>) -> Self {
+            Self {
+                server,
+                port,
+                api_key,
+                events,
+            }
+        }
+
+        async fn try_establish(&self) -> Result<TeamspeakConnection, EstablishError> {
+            let conn = TeamspeakConnection::connect(&self.server, self.port)
+                .await
+                .map_err(|e| EstablishError::Retry(format!("Connect teamspeak error: {:?}", e)))?;
+            conn.login(&self.api_key).await.map_err(|e| {
+                // Negative code means error is produced locally (e.g. connection closed)
+                if e.code() >= 0 {
+                    EstablishError::Fatal(anyhow!("Login failure, {:?}", e))
+                } else {
+                    EstablishError::Retry(format!("Login failure, {:?}", e))
+                }
+            })?;
+            conn.register_event(&self.events)
+                .await
+                .map_err(|e| EstablishError::Retry(format!("Register event failure, {:?}", e)))?;
+            Ok(conn)
+        }
+
+        /// Connect, login and register events.
+        ///
+        /// Only return error if server refuse our api key, other errors will be retried.
+        pub async fn establish(&self) -> anyhow::Result<TeamspeakConnection> {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                match self.try_establish().await {
+                    Ok(conn) => {
+                        info!("Connected to {}:{}", self.server, self.port);
+                        return Ok(conn);
+                    }
+                    Err(EstablishError::Fatal(e)) => return Err(e),
+                    Err(EstablishError::Retry(e)) => {
+                        warn!("{}, retry in {}s", e, backoff.as_secs());
+                    }
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+pub use inner::Supervisor;
+
+#[cfg(test)]
+mod test {
+    use super::Supervisor;
+    use crate::datastructures::EventKind;
+    use crate::mock_server::MockServer;
+    use tokio::time::Duration;
+
+    fn supervisor(server: &MockServer, api_key: &str) -> Supervisor {
+        Supervisor::new(
+            "127.0.0.1".to_string(),
+            server.port(),
+            api_key.to_string(),
+            vec![EventKind::Poke],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let server = MockServer::start("mock key").await;
+        let supervisor = supervisor(&server, "mock key");
+
+        let conn = supervisor.establish().await.unwrap();
+        server.disconnect_all();
+        tokio::time::timeout(Duration::from_secs(5), conn.closed())
+            .await
+            .unwrap();
+
+        let conn = supervisor.establish().await.unwrap();
+        assert!(!conn.is_closed());
+        let received = server.received();
+        assert_eq!(
+            received
+                .iter()
+                .filter(|line| line.starts_with("auth "))
+                .count(),
+            2
+        );
+        assert!(received
+            .contains(&"clientnotifyregister schandlerid=0 event=notifyclientpoke".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_refused_api_key_is_fatal() {
+        let server = MockServer::start("mock key").await;
+        let ret = tokio::time::timeout(
+            Duration::from_secs(5),
+            supervisor(&server, "wrong key").establish(),
+        )
+        .await
+        .unwrap();
+        assert!(ret.is_err());
+    }
+}
@@ -1,17 +1,111 @@
 mod ts_socket {
     use crate::datastructures::{
-        FromQueryString, NotifyTextMessage, QueryError, QueryResult, QueryStatus, SchandlerId,
+        find_by_nickname, Channel, Client, ClientSelector, EventKind, FromQueryString,
+        MessageTarget, QueryError, QueryResult, QueryStatus, SchandlerId, ServerName, WhoAmI,
     };
+    use crate::escape::escape;
     use anyhow::anyhow;
     use log::{error, trace, warn};
-    use std::time::Duration;
-    use tokio::io::{AsyncReadExt, AsyncWriteExt, Interest};
+    use std::collections::VecDeque;
+    use tap::TapFallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
     use tokio::net::TcpStream;
+    use tokio::sync::{broadcast, mpsc, oneshot, watch};
 
     const BUFFER_SIZE: usize = 512;
+    const NOTIFY_CHANNEL_CAPACITY: usize = 256;
+    const REQUEST_CHANNEL_CAPACITY: usize = 64;
+    const REGISTERED_EVENTS: &[&str] = &[
+        "notifytextmessage",
+        "notifycurrentserverconnectionchanged",
+        "notifyconnectstatuschange",
+        // Keep server model up to date
+        "notifycliententerview",
+        "notifyclientleftview",
+        "notifyclientmoved",
+        "notifyclientupdated",
+        "notifytalkstatuschange",
+        "notifychannelcreated",
+        "notifychanneledited",
+        "notifychannelmoved",
+        "notifychanneldeleted",
+    ];
+    const LINE_SEPARATOR: u8 = b'\n';
 
+    /// Split ClientQuery byte stream into whole lines.
+    ///
+    /// Incomplete lines are kept in buffer until the rest of data arrives.
+    #[derive(Debug, Default)]
+    pub struct LineCodec {
+        buffer: Vec<u8>,
+    }
+
+    impl LineCodec {
+        pub fn feed(&mut self, data: &[u8]) {
+            self.buffer.extend_from_slice(data);
+        }
+
+        /// Pop the next non-empty line from buffer, `None` if no complete line can be found.
+        pub fn next_line(&mut self) -> Option<String> {
+            while let Some(pos) = self.buffer.iter().position(|b| *b == LINE_SEPARATOR) {
+                let raw = self.buffer.drain(..=pos).collect::<Vec<_>>();
+                // Server use "\n\r" as line separator, so leading '\r' belongs to previous line
+                let line = String::from_utf8_lossy(&raw);
+                let line = line.trim_matches(|c| c == '\r' || c == '\n');
+                if !line.is_empty() {
+                    return Some(line.to_string());
+                }
+            }
+            None
+        }
+    }
+
+    /// Read half of connection with its line codec.
+    struct LineReader {
+        reader: OwnedReadHalf,
+        codec: LineCodec,
+    }
+
+    impl LineReader {
+        /// Read next whole line from server.
+        ///
+        /// This function is cancel safe, partial line will be kept in codec buffer.
+        async fn read_line(&mut self) -> anyhow::Result<String> {
+            let mut buffer = [0u8; BUFFER_SIZE];
+            loop {
+                if let Some(line) = self.codec.next_line() {
+                    trace!("receive => {:?}", &line);
+                    return Ok(line);
+                }
+                let size = self
+                    .reader
+                    .read(&mut buffer)
+                    .await
+                    .map_err(|e| anyhow!("Got error while read data: {:?}", e))?;
+                if size == 0 {
+                    return Err(anyhow!("Connection closed by remote"));
+                }
+                self.codec.feed(&buffer[..size]);
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Request {
+        payload: String,
+        reply: oneshot::Sender<String>,
+    }
+
+    /// Cloneable handle of ClientQuery connection.
+    ///
+    /// Every request is queued to the IO task, which matches replies to requests in FIFO order,
+    /// so multiple tasks can issue queries at the same time.
+    #[derive(Clone, Debug)]
     pub struct TeamspeakConnection {
-        conn: TcpStream,
+        request_sender: mpsc::Sender<Request>,
+        notify_sender: broadcast::Sender<String>,
+        alive: watch::Receiver<()>,
     }
 
     impl TeamspeakConnection {
@@ -26,70 +120,106 @@ mod ts_socket {
             Err(QueryError::static_empty_response())
         }
 
-        // credit by: Coelacanthus
-        pub async fn wait_readable(&mut self) -> anyhow::Result<bool> {
-            Ok(self.conn.ready(Interest::READABLE).await?.is_readable())
+        fn is_notification(line: &str) -> bool {
+            line.starts_with("notify")
         }
 
-        pub async fn read_data(&mut self) -> anyhow::Result<Option<String>> {
-            let mut buffer = [0u8; BUFFER_SIZE];
-            let mut ret = String::new();
-            loop {
-                let size = if let Ok(data) =
-                    tokio::time::timeout(Duration::from_secs(2), self.conn.read(&mut buffer)).await
-                {
-                    match data {
-                        Ok(size) => size,
-                        Err(e) => return Err(anyhow!("Got error while read data: {:?}", e)),
-                    }
-                } else {
-                    return Ok(None);
-                };
+        /// Subscribe every `notify*` line send by server.
+        pub fn subscribe(&self) -> broadcast::Receiver<String> {
+            self.notify_sender.subscribe()
+        }
 
-                ret.push_str(&String::from_utf8_lossy(&buffer[..size]));
-                if size < BUFFER_SIZE || (ret.contains("error id=") && ret.ends_with("\n\r")) {
-                    break;
-                }
+        pub fn is_closed(&self) -> bool {
+            self.alive.has_changed().is_err()
+        }
+
+        /// Wait until IO task exited, e.g. connection closed by remote.
+        pub async fn closed(&self) {
+            // Only return error if sender dropped
+            self.alive.clone().changed().await.ok();
+        }
+
+        /// Hide credential in payload before it is logged.
+        fn redact(payload: &str) -> &str {
+            if payload.starts_with("auth ") {
+                return "auth apikey=<redacted>";
             }
-            trace!("receive => {:?}", &ret);
-            Ok(Some(ret))
+            payload
         }
 
-        async fn write_data(&mut self, payload: &str) -> anyhow::Result<()> {
-            debug_assert!(payload.ends_with("\n\r"));
-            trace!("send => {:?}", payload);
-            self.conn
-                .write(payload.as_bytes())
-                .await
-                .map(|size| {
-                    if size != payload.as_bytes().len() {
-                        error!(
-                            "Error payload size mismatch! expect {} but {} found. payload: {:?}",
-                            payload.as_bytes().len(),
-                            size,
-                            payload
-                        )
+        async fn io_loop(
+            mut reader: LineReader,
+            mut writer: OwnedWriteHalf,
+            mut request_receiver: mpsc::Receiver<Request>,
+            notify_sender: broadcast::Sender<String>,
+            _alive: watch::Sender<()>,
+        ) -> anyhow::Result<()> {
+            let mut pending: VecDeque<oneshot::Sender<String>> = VecDeque::new();
+            let mut block = Vec::new();
+            loop {
+                tokio::select! {
+                    request = request_receiver.recv() => {
+                        let Some(request) = request else {
+                            return Ok(());
+                        };
+                        trace!("send => {:?}", Self::redact(&request.payload));
+                        writer
+                            .write_all(request.payload.as_bytes())
+                            .await
+                            .map_err(|e| anyhow!("Got error while send data: {:?}", e))?;
+                        pending.push_back(request.reply);
                     }
-                })
-                .map_err(|e| anyhow!("Got error while send data: {:?}", e))?;
-            Ok(())
+                    line = reader.read_line() => {
+                        let line = line?;
+                        if Self::is_notification(&line) {
+                            // Error only if no subscriber, which is fine
+                            notify_sender.send(line).ok();
+                            continue;
+                        }
+                        if pending.is_empty() {
+                            warn!("Unexpected line without pending command: {:?}", line);
+                            continue;
+                        }
+                        let finished = line.starts_with("error ");
+                        block.push(line);
+                        if finished {
+                            // Receiver may be dropped if caller is cancelled
+                            pending
+                                .pop_front()
+                                .unwrap()
+                                .send(block.join("\n"))
+                                .ok();
+                            block.clear();
+                        }
+                    }
+                }
+            }
         }
 
-        pub async fn keep_alive(&mut self) -> QueryResult<bool> {
+        pub async fn keep_alive(&self) -> QueryResult<bool> {
             let line = self.query_one_non_error::<String>("whoami\n\r").await?;
             Ok(line.contains("clid=") && line.contains("cid="))
         }
 
-        async fn basic_operation(&mut self, payload: &str) -> QueryResult<()> {
+        async fn basic_operation(&self, payload: &str) -> QueryResult<()> {
             let data = self.write_and_read(payload).await?;
             Self::decode_status(data).map(|_| ())
         }
 
-        async fn write_and_read(&mut self, payload: &str) -> anyhow::Result<String> {
-            self.write_data(payload).await?;
-            self.read_data()
-                .await?
-                .ok_or_else(|| anyhow!("Return data is None"))
+        /// Queue payload to IO task, and wait lines until `error id=` status line, status line included.
+        async fn write_and_read(&self, payload: &str) -> anyhow::Result<String> {
+            debug_assert!(payload.ends_with("\n\r"));
+            let (reply, receiver) = oneshot::channel();
+            self.request_sender
+                .send(Request {
+                    payload: payload.to_string(),
+                    reply,
+                })
+                .await
+                .map_err(|_| anyhow!("Connection is closed"))?;
+            receiver
+                .await
+                .map_err(|_| anyhow!("Connection closed before reply received"))
         }
 
         pub async fn connect(server: &str, port: u16) -> anyhow::Result<Self> {
@@ -97,34 +227,82 @@ mod ts_socket {
                 .await
                 .map_err(|e| anyhow!("Got error while connect to {}:{} {:?}", server, port, e))?;
 
-            let mut self_ = Self { conn };
+            let (reader, writer) = conn.into_split();
+            let mut reader = LineReader {
+                reader,
+                codec: Default::default(),
+            };
 
-            tokio::time::sleep(Duration::from_millis(10)).await;
-
-            let content = self_
-                .read_data()
+            let header = reader
+                .read_line()
                 .await
                 .map_err(|e| anyhow!("Got error in connect while read content: {:?}", e))?;
+            if !header.eq("TS3 Client") {
+                warn!("Unexpected header: {:?}", header);
+            }
 
-            if content.is_none() {
-                warn!("Read none data.");
+            // Skip welcome message until server tell us current selected tab
+            loop {
+                let line = reader
+                    .read_line()
+                    .await
+                    .map_err(|e| anyhow!("Got error in connect while read content: {:?}", e))?;
+                if line.starts_with("selected ") {
+                    break;
+                }
             }
 
-            Ok(self_)
+            let (request_sender, request_receiver) = mpsc::channel(REQUEST_CHANNEL_CAPACITY);
+            let notify_sender = broadcast::channel(NOTIFY_CHANNEL_CAPACITY).0;
+            let (alive_sender, alive) = watch::channel(());
+
+            tokio::spawn({
+                let notify_sender = notify_sender.clone();
+                async move {
+                    Self::io_loop(
+                        reader,
+                        writer,
+                        request_receiver,
+                        notify_sender,
+                        alive_sender,
+                    )
+                    .await
+                    .tap_err(|e| error!("Connection IO task exited: {:?}", e))
+                    .ok();
+                }
+            });
+
+            Ok(Self {
+                request_sender,
+                notify_sender,
+                alive,
+            })
         }
 
-        pub async fn register_event(&mut self) -> QueryResult<()> {
-            self.basic_operation("clientnotifyregister schandlerid=0 event=notifytextmessage\n\r")
-                .await
+        /// Register events which are always needed, and optional events specified by user.
+        pub async fn register_event(&self, extra: &[EventKind]) -> QueryResult<()> {
+            for event in REGISTERED_EVENTS.iter().copied().chain(
+                extra
+                    .iter()
+                    .map(|kind| kind.notify_name())
+                    .filter(|name| !REGISTERED_EVENTS.contains(name)),
+            ) {
+                self.basic_operation(&format!(
+                    "clientnotifyregister schandlerid=0 event={}\n\r",
+                    event
+                ))
+                .await?;
+            }
+            Ok(())
         }
 
-        pub async fn login(&mut self, api_key: &str) -> QueryResult<()> {
-            let payload = format!("auth apikey={}\n\r", api_key);
+        pub async fn login(&self, api_key: &str) -> QueryResult<()> {
+            let payload = format!("auth apikey={}\n\r", escape(api_key));
             self.basic_operation(payload.as_str()).await
         }
 
         async fn send_text_message(
-            &mut self,
+            &self,
             mode: i64,
             server_id: i64,
             client_id: i64,
@@ -135,36 +313,13 @@ mod ts_socket {
                 server_id = server_id,
                 mode = mode,
                 client_id = client_id,
-                text = Self::escape(text)
+                text = escape(text)
             );
-            let data = self.write_and_read(&payload).await.map(|s| {
-                if s.contains("\n\r") {
-                    s.split_once("\n\r").unwrap().0.to_string()
-                } else {
-                    s
-                }
-            })?;
-            if data.starts_with("notifytextmessage") {
-                let (_, data) = data
-                    .split_once("notifytextmessage ")
-                    .ok_or_else(|| QueryError::send_message_error(data.clone()))?;
-                let r = NotifyTextMessage::from_query(data)
-                    .map_err(|_| QueryError::decode_error(data))?;
-                //trace!("{:?} {:?}", r.msg(), text);
-                if !r.msg().eq(text) {
-                    return Err(QueryError::send_message_error(
-                        "None (No equal)".to_string(),
-                    ));
-                }
-            } else {
-                return Self::decode_status(data).map(|_| ());
-            }
-            Ok(())
+            self.basic_operation(&payload).await
         }
 
-        #[allow(dead_code)]
         pub async fn send_private_message(
-            &mut self,
+            &self,
             server_id: i64,
             client_id: i64,
             text: &str,
@@ -172,18 +327,53 @@ mod ts_socket {
             self.send_text_message(1, server_id, client_id, text).await
         }
 
-        pub async fn send_channel_message(
-            &mut self,
+        pub async fn send_channel_message(&self, server_id: i64, text: &str) -> QueryResult<()> {
+            self.send_text_message(2, server_id, 0, text).await
+        }
+
+        pub async fn send_server_message(&self, server_id: i64, text: &str) -> QueryResult<()> {
+            self.send_text_message(3, server_id, 0, text).await
+        }
+
+        pub async fn send_message(
+            &self,
             server_id: i64,
+            target: &MessageTarget,
             text: &str,
         ) -> QueryResult<()> {
-            self.send_text_message(2, server_id, 0, text).await
+            match target {
+                MessageTarget::Client(selector) => {
+                    let client_id = self.resolve_client(server_id, selector).await?;
+                    self.send_private_message(server_id, client_id, text).await
+                }
+                MessageTarget::Channel => self.send_channel_message(server_id, text).await,
+                MessageTarget::Server => self.send_server_message(server_id, text).await,
+            }
         }
 
-        fn escape(s: &str) -> String {
-            s.replace('\\', "\\\\")
-                .replace(' ', "\\s")
-                .replace('/', "\\/")
+        pub async fn get_clients(&self, server_id: i64) -> QueryResult<Vec<Client>> {
+            self.query_operation_non_error(&format!(
+                "clientlist schandlerid={} -uid -away -voice\n\r",
+                server_id
+            ))
+            .await
+        }
+
+        /// Find client id by nickname, ServerQuery clients are ignored.
+        pub async fn resolve_client(
+            &self,
+            server_id: i64,
+            selector: &ClientSelector,
+        ) -> QueryResult<i64> {
+            let name = match selector {
+                ClientSelector::Id(id) => return Ok(*id),
+                ClientSelector::Nickname(name) => name,
+            };
+            let clients = self.get_clients(server_id).await?;
+            find_by_nickname(clients.iter().filter(|c| c.is_client()), name, |c| {
+                c.client_nickname()
+            })
+            .map(|client| client.client_id())
         }
 
         fn decode_status_with_result<T: FromQueryString + Sized>(
@@ -195,7 +385,10 @@ mod ts_socket {
                 if !line.starts_with("error ") {
                     let mut v = Vec::new();
                     for element in line.split('|') {
-                        v.push(T::from_query(element)?);
+                        v.push(
+                            T::from_query(element)
+                                .map_err(|_| QueryError::decode_error(element))?,
+                        );
                     }
                     return Ok(Some(v));
                 }
@@ -203,31 +396,336 @@ mod ts_socket {
             Ok(None)
         }
 
+        /// Empty list is returned if server reply no result line, e.g. empty channel list.
         async fn query_operation_non_error<T: FromQueryString + Sized>(
-            &mut self,
+            &self,
             payload: &str,
         ) -> QueryResult<Vec<T>> {
             let data = self.write_and_read(payload).await?;
-            let ret = Self::decode_status_with_result(data)?;
-            Ok(ret
-                .ok_or_else(|| panic!("Can't find result line, payload => {}", payload))
-                .unwrap())
+            Ok(Self::decode_status_with_result(data)?.unwrap_or_default())
         }
 
         async fn query_one_non_error<T: FromQueryString + Sized>(
-            &mut self,
+            &self,
             payload: &str,
         ) -> QueryResult<T> {
             self.query_operation_non_error(payload)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(QueryError::static_empty_response)
+        }
+
+        pub async fn move_client(
+            &self,
+            server_id: i64,
+            client_id: i64,
+            channel_id: i64,
+        ) -> QueryResult<()> {
+            self.basic_operation(&format!(
+                "clientmove schandlerid={} cid={} clid={}\n\r",
+                server_id, channel_id, client_id
+            ))
+            .await
+        }
+
+        /// Update properties of our own client, `properties` should be escaped.
+        async fn update_client(&self, server_id: i64, properties: &str) -> QueryResult<()> {
+            self.basic_operation(&format!(
+                "clientupdate schandlerid={} {}\n\r",
+                server_id, properties
+            ))
+            .await
+        }
+
+        pub async fn set_nickname(&self, server_id: i64, nickname: &str) -> QueryResult<()> {
+            self.update_client(server_id, &format!("client_nickname={}", escape(nickname)))
+                .await
+        }
+
+        /// Set away with message, or clear away status if `message` is `None`.
+        pub async fn set_away(&self, server_id: i64, message: Option<&str>) -> QueryResult<()> {
+            let properties = match message {
+                Some(message) => format!("client_away=1 client_away_message={}", escape(message)),
+                None => "client_away=0".to_string(),
+            };
+            self.update_client(server_id, &properties).await
+        }
+
+        pub async fn set_input_muted(&self, server_id: i64, muted: bool) -> QueryResult<()> {
+            self.update_client(server_id, &format!("client_input_muted={}", muted as u8))
+                .await
+        }
+
+        pub async fn set_output_muted(&self, server_id: i64, muted: bool) -> QueryResult<()> {
+            self.update_client(server_id, &format!("client_output_muted={}", muted as u8))
                 .await
-                .map(|mut v| v.swap_remove(0))
+        }
+
+        pub async fn poke_client(
+            &self,
+            server_id: i64,
+            client_id: i64,
+            message: &str,
+        ) -> QueryResult<()> {
+            self.basic_operation(&format!(
+                "clientpoke schandlerid={} clid={} msg={}\n\r",
+                server_id,
+                client_id,
+                escape(message)
+            ))
+            .await
+        }
+
+        pub async fn get_channels(&self, server_id: i64) -> QueryResult<Vec<Channel>> {
+            self.query_operation_non_error(&format!("channellist schandlerid={}\n\r", server_id))
+                .await
+        }
+
+        pub async fn who_am_i(&self, server_id: i64) -> QueryResult<WhoAmI> {
+            self.query_one_non_error(&format!("whoami schandlerid={}\n\r", server_id))
+                .await
+        }
+
+        pub async fn get_server_tabs(&self) -> QueryResult<Vec<SchandlerId>> {
+            self.query_operation_non_error("serverconnectionhandlerlist\n\r")
+                .await
+        }
+
+        /// Get server name and unique identifier of tab, return error if tab is not connected.
+        pub async fn get_server_name(&self, server_id: i64) -> QueryResult<ServerName> {
+            self.query_one_non_error(&format!(
+                "servervariable schandlerid={} virtualserver_name virtualserver_unique_identifier\n\r",
+                server_id
+            ))
+            .await
         }
 
         // TODO: Need test in no connection
-        pub async fn get_current_server_tab(&mut self) -> QueryResult<SchandlerId> {
+        pub async fn get_current_server_tab(&self) -> QueryResult<SchandlerId> {
             self.query_one_non_error("currentschandlerid\n\r").await
         }
     }
 }
 
 pub use ts_socket::TeamspeakConnection;
+
+#[cfg(test)]
+mod test {
+    use super::ts_socket::LineCodec;
+    use super::TeamspeakConnection;
+    use crate::datastructures::ClientSelector;
+    use crate::mock_server::{
+        MockServer, MOCK_CLIENT_ID, MOCK_NICKNAME, MOCK_SERVER_ID, MOCK_SERVER_NAME,
+        MOCK_SERVER_UID,
+    };
+    use std::future::Future;
+    use tokio::time::Duration;
+
+    async fn timeout<F: Future>(future: F) -> F::Output {
+        tokio::time::timeout(Duration::from_secs(5), future)
+            .await
+            .expect("timed out")
+    }
+
+    #[test]
+    fn test_line_codec_split_read() {
+        let mut codec = LineCodec::default();
+        // Line split across several reads
+        codec.feed(b"clid=1 ");
+        assert_eq!(codec.next_line(), None);
+        codec.feed(b"cid=2 client_nickname=");
+        assert_eq!(codec.next_line(), None);
+        codec.feed(b"Alice\n");
+        assert_eq!(
+            codec.next_line().as_deref(),
+            Some("clid=1 cid=2 client_nickname=Alice")
+        );
+        assert_eq!(codec.next_line(), None);
+
+        // Separator split between two reads
+        codec.feed(b"error id=0 msg=ok\n");
+        assert_eq!(codec.next_line().as_deref(), Some("error id=0 msg=ok"));
+        codec.feed(b"\rselected schandlerid=1\n");
+        assert_eq!(codec.next_line().as_deref(), Some("selected schandlerid=1"));
+
+        // Partial line stays in buffer
+        codec.feed(b"\rnotifytalk");
+        assert_eq!(codec.next_line(), None);
+        codec.feed(b"statuschange status=1\n\r");
+        assert_eq!(
+            codec.next_line().as_deref(),
+            Some("notifytalkstatuschange status=1")
+        );
+        assert_eq!(codec.next_line(), None);
+    }
+
+    #[test]
+    fn test_line_codec_multiple_lines() {
+        let mut codec = LineCodec::default();
+        codec.feed(b"\n\rclid=5 cid=1\n\r\n\rerror id=0 msg=ok\n\rnotifyclient");
+        assert_eq!(codec.next_line().as_deref(), Some("clid=5 cid=1"));
+        // Empty line is skipped
+        assert_eq!(codec.next_line().as_deref(), Some("error id=0 msg=ok"));
+        assert_eq!(codec.next_line(), None);
+        codec.feed(b"moved clid=5\n\r");
+        assert_eq!(
+            codec.next_line().as_deref(),
+            Some("notifyclientmoved clid=5")
+        );
+    }
+
+    async fn connect(server: &MockServer) -> TeamspeakConnection {
+        let conn = TeamspeakConnection::connect("127.0.0.1", server.port())
+            .await
+            .unwrap();
+        conn.login("mock key").await.unwrap();
+        conn
+    }
+
+    #[tokio::test]
+    async fn test_login_and_send() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+        let mut notify = conn.subscribe();
+
+        conn.register_event(&[]).await.unwrap();
+        assert_eq!(
+            conn.who_am_i(MOCK_SERVER_ID).await.unwrap().client_id(),
+            MOCK_CLIENT_ID
+        );
+        assert_eq!(
+            conn.get_current_server_tab().await.unwrap().schandler_id(),
+            MOCK_SERVER_ID
+        );
+        let server_name = conn.get_server_name(MOCK_SERVER_ID).await.unwrap();
+        assert_eq!(server_name.name(), MOCK_SERVER_NAME);
+        assert_eq!(server_name.unique_id(), MOCK_SERVER_UID);
+        let client = conn
+            .resolve_client(
+                MOCK_SERVER_ID,
+                &ClientSelector::from(MOCK_NICKNAME.to_lowercase().as_str()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(client, MOCK_CLIENT_ID);
+        let channels = conn.get_channels(MOCK_SERVER_ID).await.unwrap();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[1].channel_name(), "Away From Keyboard");
+
+        conn.send_channel_message(MOCK_SERVER_ID, "hello world")
+            .await
+            .unwrap();
+        let echo = timeout(notify.recv()).await.unwrap();
+        assert!(echo.starts_with("notifytextmessage "));
+        assert!(echo.contains(r"msg=hello\sworld"));
+        assert!(server.received().contains(
+            &r"sendtextmessage schandlerid=1 targetmode=2 target=0 msg=hello\sworld".to_string()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_api_key() {
+        let server = MockServer::start("mock key").await;
+        let conn = TeamspeakConnection::connect("127.0.0.1", server.port())
+            .await
+            .unwrap();
+        let err = conn.login("wrong key").await.unwrap_err();
+        assert!(err.code() > 0);
+        assert!(conn.who_am_i(MOCK_SERVER_ID).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scripted_error_and_notification() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+        let mut notify = conn.subscribe();
+
+        server.fail_next("sendtextmessage", 524, "client is flooding");
+        let err = conn
+            .send_channel_message(MOCK_SERVER_ID, "hello")
+            .await
+            .unwrap_err();
+        assert!(err.is_flooding());
+        conn.send_channel_message(MOCK_SERVER_ID, "hello")
+            .await
+            .unwrap();
+        assert!(timeout(notify.recv()).await.unwrap().contains("msg=hello"));
+
+        server.inject("notifyclientpoke schandlerid=1 msg=hi invokerid=7 invokername=Bob");
+        assert!(timeout(notify.recv())
+            .await
+            .unwrap()
+            .starts_with("notifyclientpoke "));
+    }
+
+    #[tokio::test]
+    async fn test_client_actions() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+
+        conn.move_client(MOCK_SERVER_ID, MOCK_CLIENT_ID, 2)
+            .await
+            .unwrap();
+        conn.set_nickname(MOCK_SERVER_ID, "New Name").await.unwrap();
+        conn.set_away(MOCK_SERVER_ID, Some("be right back"))
+            .await
+            .unwrap();
+        conn.set_away(MOCK_SERVER_ID, None).await.unwrap();
+        conn.set_input_muted(MOCK_SERVER_ID, true).await.unwrap();
+        conn.set_output_muted(MOCK_SERVER_ID, true).await.unwrap();
+        conn.poke_client(MOCK_SERVER_ID, 7, "wake up")
+            .await
+            .unwrap();
+        let received = server.received();
+        for payload in [
+            "clientmove schandlerid=1 cid=2 clid=5",
+            r"clientupdate schandlerid=1 client_nickname=New\sName",
+            r"clientupdate schandlerid=1 client_away=1 client_away_message=be\sright\sback",
+            "clientupdate schandlerid=1 client_away=0",
+            "clientupdate schandlerid=1 client_input_muted=1",
+            "clientupdate schandlerid=1 client_output_muted=1",
+            r"clientpoke schandlerid=1 clid=7 msg=wake\sup",
+        ] {
+            assert!(received.contains(&payload.to_string()), "{}", payload);
+        }
+
+        server.fail_next("clientmove", 770, "already member of channel");
+        let err = conn
+            .move_client(MOCK_SERVER_ID, MOCK_CLIENT_ID, 1)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), 770);
+        assert_eq!(err.to_string(), "already member of channel(770)");
+    }
+
+    #[tokio::test]
+    async fn test_empty_result() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+
+        server.reply_next("channellist", &[]);
+        assert!(conn.get_channels(MOCK_SERVER_ID).await.unwrap().is_empty());
+        server.reply_next("whoami", &[]);
+        assert_eq!(conn.who_am_i(MOCK_SERVER_ID).await.unwrap_err().code(), -1);
+        // Connection is still usable
+        assert_eq!(
+            conn.who_am_i(MOCK_SERVER_ID).await.unwrap().client_id(),
+            MOCK_CLIENT_ID
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_closed() {
+        let server = MockServer::start("mock key").await;
+        let conn = connect(&server).await;
+        server.disconnect_all();
+        timeout(conn.closed()).await;
+        assert!(conn.is_closed());
+        let err = conn
+            .send_channel_message(MOCK_SERVER_ID, "hello")
+            .await
+            .unwrap_err();
+        assert!(err.code() < 0);
+    }
+}